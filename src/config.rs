@@ -0,0 +1,278 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::datastream::parser::ParseMode;
+use crate::datastream::pose_file::PoseAssociation;
+use crate::datastream::selection::ScanSelection;
+use crate::drawing::{
+    animation::AnimationParameter, drawing_parameter::DrawingParameter,
+    map_server::MapServerParameter, overlay::OverlayParameter,
+};
+use crate::rendering::map_creator_parameter::MapCreatorParameter;
+
+/// Settings of all the commands, stored as TOML or YAML file to reproduce
+/// an output. Missing entries take their default value.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub input: InputConfig,
+    pub map: MapCreatorParameter,
+    pub drawing: DrawingParameter,
+    pub overlays: OverlayParameter,
+    pub render: RenderConfig,
+    pub animate: AnimateConfig,
+    pub export: ExportConfig,
+    pub stats: StatsConfig,
+    pub evaluate: EvaluateConfig,
+}
+
+/// How to read the logfile
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputConfig {
+    ///< how to deal with malformed lines
+    pub parse_mode: ParseMode,
+    ///< scans of the logfile which go into the map
+    pub selection: ScanSelection,
+    ///< trajectory replacing the poses of the scans
+    pub poses: Option<PathBuf>,
+    ///< how to associate the scans with the poses of the trajectory
+    pub associate_by: PoseAssociation,
+    ///< markers, lines and areas drawn on top of the map
+    pub annotations: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderConfig {
+    ///< indices of the scans to highlight
+    pub scan: Vec<usize>,
+    ///< draw the path of the robot
+    pub draw_path: bool,
+    pub output: PathBuf,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            scan: Vec::new(),
+            draw_path: false,
+            output: PathBuf::from("log2gfx.png"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnimateConfig {
+    pub animation: AnimationParameter,
+    ///< build the map while animating
+    pub incremental: bool,
+    pub output: PathBuf,
+}
+
+impl Default for AnimateConfig {
+    fn default() -> Self {
+        Self {
+            animation: AnimationParameter::default(),
+            incremental: false,
+            output: PathBuf::from("."),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExportConfig {
+    pub map_server: MapServerParameter,
+    pub output: PathBuf,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            map_server: MapServerParameter::default(),
+            output: PathBuf::from("map.pgm"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatsConfig {
+    ///< width of the intervals of the range histogram in meter
+    pub bin_width: f64,
+    ///< write JSON instead of text
+    pub json: bool,
+    pub output: PathBuf,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        Self {
+            bin_width: 1.,
+            json: false,
+            output: PathBuf::from("-"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EvaluateConfig {
+    ///< reference trajectory, the TRUEPOS messages of the log if not given
+    pub reference: Option<PathBuf>,
+    ///< lengths of the segments for the relative pose error in meter
+    pub segment_lengths: Vec<f64>,
+    ///< write JSON instead of text
+    pub json: bool,
+    pub output: PathBuf,
+    ///< render the map with the path and the aligned reference path
+    pub map: Option<PathBuf>,
+}
+
+impl Default for EvaluateConfig {
+    fn default() -> Self {
+        Self {
+            reference: None,
+            segment_lengths: vec![1., 5.],
+            json: false,
+            output: PathBuf::from("-"),
+            map: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file could not be read or written
+    Io {
+        filename: PathBuf,
+        source: io::Error,
+    },
+    /// The content of the file is not a valid config
+    Format { filename: PathBuf, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { filename, source } => {
+                write!(f, "{}: {}", filename.display(), source)
+            }
+            ConfigError::Format { filename, message } => {
+                write!(f, "{}: {}", filename.display(), message.trim_end())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            ConfigError::Format { .. } => None,
+        }
+    }
+}
+
+/// Files with the extension `yaml` or `yml` are YAML, all others TOML
+fn is_yaml(filename: &Path) -> bool {
+    matches!(
+        filename.extension().and_then(|e| e.to_str()),
+        Some("yaml" | "yml")
+    )
+}
+
+/// Read a TOML or YAML file
+pub(crate) fn load_file<T: DeserializeOwned>(filename: &Path) -> Result<T, ConfigError> {
+    let content = fs::read_to_string(filename).map_err(|source| ConfigError::Io {
+        filename: filename.to_path_buf(),
+        source,
+    })?;
+    let format_error = |message: String| ConfigError::Format {
+        filename: filename.to_path_buf(),
+        message,
+    };
+    if is_yaml(filename) {
+        serde_yaml::from_str(&content).map_err(|e| format_error(e.to_string()))
+    } else {
+        toml::from_str(&content).map_err(|e| format_error(e.to_string()))
+    }
+}
+
+impl Config {
+    pub fn load(filename: &Path) -> Result<Self, ConfigError> {
+        load_file(filename)
+    }
+
+    pub fn save(&self, filename: &Path) -> Result<(), ConfigError> {
+        let format_error = |message: String| ConfigError::Format {
+            filename: filename.to_path_buf(),
+            message,
+        };
+        let content = if is_yaml(filename) {
+            serde_yaml::to_string(self).map_err(|e| format_error(e.to_string()))?
+        } else {
+            toml::to_string(self).map_err(|e| format_error(e.to_string()))?
+        };
+        fs::write(filename, content).map_err(|source| ConfigError::Io {
+            filename: filename.to_path_buf(),
+            source,
+        })
+    }
+}
+
+/// Filename of the config stored next to an output, `None` for stdout.
+///
+/// Files get the extension `toml`, a folder of images the file `log2gfx.toml`.
+pub fn config_filename(output: &Path) -> Option<PathBuf> {
+    if output == Path::new("-") {
+        None
+    } else if output.extension().is_some() {
+        Some(output.with_extension("toml"))
+    } else {
+        Some(output.join("log2gfx.toml"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut config = Config::default();
+        config.map.resolution = 0.05;
+        config.map.offset = nalgebra::Isometry2::new(nalgebra::Vector2::new(1., -2.), 0.5);
+        config.drawing.unknown_color = [1, 2, 3, 4];
+        config.input.poses = Some(PathBuf::from("poses.csv"));
+        config.animate.animation.end = Some(10);
+        config.export.map_server.free_thresh = 0.1;
+
+        let text = toml::to_string(&config).unwrap();
+        let parsed: Config = toml::from_str(&text).unwrap();
+        assert_eq!(toml::to_string(&parsed).unwrap(), text);
+        assert!((parsed.map.offset.rotation.angle() - 0.5).abs() < 1e-12);
+
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        let parsed: Config = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(toml::to_string(&parsed).unwrap(), text);
+    }
+
+    #[test]
+    fn partial_config() {
+        let config: Config = toml::from_str(
+            "[map]\nresolution = 0.2\nmap_model = \"log-odds\"\n\n[animate.animation]\nstep = 5\n",
+        )
+        .unwrap();
+        assert_eq!(config.map.resolution, 0.2);
+        assert_eq!(config.map.max_range, 20.);
+        assert_eq!(config.animate.animation.step, 5);
+        assert!(toml::from_str::<Config>("[map]\nresolutoin = 0.2\n").is_err());
+    }
+}
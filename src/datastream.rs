@@ -1,3 +1,19 @@
 pub mod parser;
 pub mod parser_carmen;
+pub mod parser_g2o;
+pub mod pose_file;
 pub mod robot_data;
+pub mod selection;
+
+use std::path::PathBuf;
+
+use parser::Parser;
+
+/// Select the parser by the extension, `g2o` and `graph` files are read as
+/// g2o/TORO graphs and all other files as CARMEN logs
+pub fn parser_for(filename: PathBuf) -> Box<dyn Parser> {
+    match filename.extension().and_then(|e| e.to_str()) {
+        Some("g2o") | Some("graph") => Box::new(parser_g2o::G2oFile { filename }),
+        _ => Box::new(parser_carmen::CarmenFile { filename }),
+    }
+}
@@ -1,5 +1,316 @@
+extern crate nalgebra as na;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::str::{FromStr, SplitWhitespace};
+
+use serde::{Deserialize, Serialize};
+
 use super::robot_data;
 
+/// How to deal with lines that do not match the expected format
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ParseMode {
+    /// Skip malformed lines and keep on parsing
+    #[default]
+    Lenient,
+    /// Stop at the first malformed line
+    Strict,
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    /// The file could not be opened or read
+    Io {
+        filename: PathBuf,
+        source: io::Error,
+    },
+    /// A field of a line is missing or cannot be parsed
+    Malformed {
+        filename: PathBuf,
+        line: usize,
+        field: &'static str,
+        token: Option<String>,
+    },
+    /// A pose graph does not contain any laser data for its vertices
+    NoLaserData { filename: PathBuf },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Io { filename, source } => {
+                write!(f, "{}: {}", filename.display(), source)
+            }
+            ParseError::Malformed {
+                filename,
+                line,
+                field,
+                token: Some(token),
+            } => write!(
+                f,
+                "{}:{}: invalid value '{}' for field '{}'",
+                filename.display(),
+                line,
+                token,
+                field
+            ),
+            ParseError::Malformed {
+                filename,
+                line,
+                field,
+                token: None,
+            } => write!(
+                f,
+                "{}:{}: missing field '{}'",
+                filename.display(),
+                line,
+                field
+            ),
+            ParseError::NoLaserData { filename } => write!(
+                f,
+                "{}: the graph has no ROBOTLASER lines with the scans of its vertices",
+                filename.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::Io { source, .. } => Some(source),
+            ParseError::Malformed { .. } | ParseError::NoLaserData { .. } => None,
+        }
+    }
+}
+
+/// Missing or invalid field within a single line
+#[derive(Debug)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub token: Option<String>,
+}
+
+impl FieldError {
+    pub fn at(self, filename: &Path, line: usize) -> ParseError {
+        ParseError::Malformed {
+            filename: filename.to_path_buf(),
+            line,
+            field: self.field,
+            token: self.token,
+        }
+    }
+}
+
+pub type FieldResult<T> = Result<T, FieldError>;
+
+/// Read the next token of a line and parse it as the given field
+pub fn next_value<T: FromStr>(tokens: &mut SplitWhitespace, field: &'static str) -> FieldResult<T> {
+    let token = tokens.next().ok_or(FieldError { field, token: None })?;
+    token.parse().map_err(|_| FieldError {
+        field,
+        token: Some(token.to_string()),
+    })
+}
+
+/// Read the next three tokens as a 2D pose given by x, y, theta
+pub fn read_pose(
+    tokens: &mut SplitWhitespace,
+    fields: [&'static str; 3],
+) -> FieldResult<na::Isometry2<f64>> {
+    let x: f64 = next_value(tokens, fields[0])?;
+    let y: f64 = next_value(tokens, fields[1])?;
+    let theta: f64 = next_value(tokens, fields[2])?;
+    Ok(na::Isometry2::new(na::Vector2::new(x, y), theta))
+}
+
+/// Number of characters of an undecodable line kept for the error message
+const MAX_TOKEN_CHARS: usize = 40;
+
+/// Data read from a file together with the lines skipped in lenient mode
+pub struct Parsed<T> {
+    pub data: Vec<T>,
+    pub skipped: Vec<ParseError>,
+}
+
+/// Read a file line by line and convert each line by `read_line`.
+///
+/// Lines for which `read_line` returns `None` are ignored, malformed lines
+/// are returned as error and the iteration continues with the next line.
+/// Only a single line is kept in memory at a time.
+pub fn read_lines<T, F>(
+    filename: &Path,
+    read_line: F,
+) -> Result<impl Iterator<Item = Result<T, ParseError>>, ParseError>
+where
+    F: FnMut(&str) -> FieldResult<Option<T>>,
+{
+    Ok(read_lines_from(
+        filename.to_path_buf(),
+        open(filename)?,
+        read_line,
+    ))
+}
+
+/// Open a file for reading it line by line
+pub(super) fn open(filename: &Path) -> Result<io::BufReader<File>, ParseError> {
+    let file = File::open(filename).map_err(|source| ParseError::Io {
+        filename: filename.to_path_buf(),
+        source,
+    })?;
+    Ok(io::BufReader::new(file))
+}
+
+/// Convert the lines of `reader` by `read_line`, see `read_lines`
+pub(super) fn read_lines_from<R, T, F>(
+    filename: PathBuf,
+    reader: R,
+    mut read_line: F,
+) -> impl Iterator<Item = Result<T, ParseError>>
+where
+    R: BufRead,
+    F: FnMut(&str) -> FieldResult<Option<T>>,
+{
+    // split the raw bytes so that a line which is not valid UTF-8 is a
+    // malformed line and not a read error
+    let lines = reader.split(b'\n').enumerate();
+    lines.filter_map(move |(i, line)| {
+        let mut line = match line {
+            Ok(line) => line,
+            Err(source) => {
+                return Some(Err(ParseError::Io {
+                    filename: filename.clone(),
+                    source,
+                }))
+            }
+        };
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        let line = match String::from_utf8(line) {
+            Ok(line) => line,
+            Err(e) => {
+                let text = String::from_utf8_lossy(e.as_bytes());
+                let error = FieldError {
+                    field: "utf-8 text",
+                    token: Some(text.chars().take(MAX_TOKEN_CHARS).collect()),
+                };
+                return Some(Err(error.at(&filename, i + 1)));
+            }
+        };
+        match read_line(&line) {
+            Ok(Some(value)) => Some(Ok(value)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e.at(&filename, i + 1))),
+        }
+    })
+}
+
+/// Drop the malformed lines of a stream in lenient mode and pass them to
+/// `on_skip`, all other errors are kept in the stream
+pub fn skip_malformed<T, I, F>(
+    values: I,
+    mode: ParseMode,
+    mut on_skip: F,
+) -> impl Iterator<Item = Result<T, ParseError>>
+where
+    I: Iterator<Item = Result<T, ParseError>>,
+    F: FnMut(ParseError),
+{
+    values.filter_map(move |value| match value {
+        Err(e @ ParseError::Malformed { .. }) if mode == ParseMode::Lenient => {
+            on_skip(e);
+            None
+        }
+        _ => Some(value),
+    })
+}
+
+/// Collect a stream, malformed lines are either skipped or abort depending on `mode`
+pub fn collect<T, I>(values: I, mode: ParseMode) -> Result<Parsed<T>, ParseError>
+where
+    I: Iterator<Item = Result<T, ParseError>>,
+{
+    let mut data = Vec::new();
+    let mut skipped = Vec::new();
+    for value in skip_malformed(values, mode, |e| skipped.push(e)) {
+        data.push(value?);
+    }
+    Ok(Parsed { data, skipped })
+}
+
+/// Read a file line by line and convert each line by `read_line`.
+///
+/// Lines for which `read_line` returns `None` are ignored, malformed lines
+/// are either skipped or abort the parsing depending on `mode`.
+pub fn parse_lines<T, F>(
+    filename: &Path,
+    mode: ParseMode,
+    read_line: F,
+) -> Result<Parsed<T>, ParseError>
+where
+    F: FnMut(&str) -> FieldResult<Option<T>>,
+{
+    collect(read_lines(filename, read_line)?, mode)
+}
+
+/// Stream of the scans of a file
+pub type Scans = Box<dyn Iterator<Item = Result<robot_data::RobotLaser, ParseError>>>;
+
 pub trait Parser {
-    fn parse(&self) -> Vec<robot_data::RobotLaser>;
+    /// Read the scans one at a time, every call starts at the beginning of the file
+    fn scans(&self) -> Result<Scans, ParseError>;
+
+    fn parse(&self, mode: ParseMode) -> Result<Parsed<robot_data::RobotLaser>, ParseError> {
+        collect(self.scans()?, mode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skip_malformed_lines() {
+        let malformed = || ParseError::Malformed {
+            filename: PathBuf::from("test.log"),
+            line: 2,
+            field: "x",
+            token: None,
+        };
+        let values = || vec![Ok(1), Err(malformed()), Ok(3)].into_iter();
+
+        let parsed = collect(values(), ParseMode::Lenient).unwrap();
+        assert_eq!(parsed.data, vec![1, 3]);
+        assert_eq!(parsed.skipped.len(), 1);
+        assert!(collect(values(), ParseMode::Strict).is_err());
+
+        let mut num_skipped = 0;
+        let streamed: Vec<i32> = skip_malformed(values(), ParseMode::Lenient, |_| num_skipped += 1)
+            .map(|v| v.unwrap())
+            .collect();
+        assert_eq!(streamed, vec![1, 3]);
+        assert_eq!(num_skipped, 1);
+    }
+
+    #[test]
+    fn skip_invalid_utf8() {
+        let lines = || {
+            let text: &[u8] = b"a\r\n\xff\xfe\nb\n";
+            read_lines_from(PathBuf::from("test.log"), text, |line| {
+                Ok(Some(line.to_string()))
+            })
+        };
+        let parsed = collect(lines(), ParseMode::Lenient).unwrap();
+        assert_eq!(parsed.data, vec!["a", "b"]);
+        assert!(matches!(
+            parsed.skipped[..],
+            [ParseError::Malformed { line: 2, .. }]
+        ));
+        assert!(collect(lines(), ParseMode::Strict).is_err());
+    }
 }
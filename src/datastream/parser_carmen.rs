@@ -1,82 +1,386 @@
 extern crate nalgebra as na;
 
-use std::fs::File;
-use std::io::{self, BufRead};
-use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::path::PathBuf;
+use std::str::SplitWhitespace;
 
-use super::parser;
+use super::parser::{
+    self, next_value, read_pose, FieldError, FieldResult, ParseError, ParseMode, Parsed,
+};
+use super::pose_file::TimedPose;
 use super::robot_data;
 
+/// Max range of the old FLASER/RLASER messages if no PARAM specifies it
+const DEFAULT_LASER_MAX_RANGE: f64 = 81.9;
+
+/// Values reserved up front for a count read from a line, a malformed count
+/// must not allocate more than the line can hold
+const MAX_RESERVED_VALUES: usize = 4096;
+
 pub struct CarmenFile {
     pub filename: PathBuf,
 }
 
-fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
-where
-    P: AsRef<Path>,
-{
-    let file = File::open(filename)?;
-    Ok(io::BufReader::new(file).lines())
+/// A single message of a CARMEN log file
+pub enum CarmenMessage {
+    RobotLaser(robot_data::RobotLaser),
+    RawLaser(robot_data::RawLaser),
+    Odometry(robot_data::Odometry),
+    TruePos(robot_data::TruePos),
+    Param(String, String),
+}
+
+/// Laser mounted on the robot as used by the old FLASER/RLASER messages
+#[derive(Debug, Clone, Copy)]
+enum LaserMount {
+    Front,
+    Rear,
 }
 
-fn read_robotlaser(line: &str) -> Option<robot_data::RobotLaser> {
-    if !line.starts_with("ROBOTLASER") {
-        return None;
+impl LaserMount {
+    fn name(&self) -> &'static str {
+        match self {
+            LaserMount::Front => "front",
+            LaserMount::Rear => "rear",
+        }
     }
-    let mut tokens = line.split_whitespace();
-    let _tag = tokens.next().unwrap();
-    let _laser_type: i32 = tokens.next().unwrap().parse().unwrap();
-    let angle: f64 = tokens.next().unwrap().parse().unwrap();
-    let _fov: f64 = tokens.next().unwrap().parse().unwrap();
-    let angular_step: f64 = tokens.next().unwrap().parse().unwrap();
-    let max_range: f64 = tokens.next().unwrap().parse().unwrap();
-    let _accuracy: f64 = tokens.next().unwrap().parse().unwrap();
-    let _remission_mode: i32 = tokens.next().unwrap().parse().unwrap();
+}
 
-    // parsing the beams
-    let num_beams: i32 = tokens.next().unwrap().parse().unwrap();
-    let mut ranges = Vec::with_capacity(num_beams as usize);
-    for _ in 0..num_beams {
-        let range: f32 = tokens.next().unwrap().parse().unwrap();
-        ranges.push(range);
+fn read_values(
+    tokens: &mut SplitWhitespace,
+    count_field: &'static str,
+    field: &'static str,
+) -> FieldResult<Vec<f32>> {
+    let num_values: usize = next_value(tokens, count_field)?;
+    let mut values = Vec::with_capacity(num_values.min(MAX_RESERVED_VALUES));
+    for _ in 0..num_values {
+        let value: f32 = next_value(tokens, field)?;
+        values.push(value);
+    }
+    Ok(values)
+}
+
+fn read_ranges(tokens: &mut SplitWhitespace) -> FieldResult<Vec<f32>> {
+    read_values(tokens, "num_readings", "range")
+}
+
+/// Read the timestamps at the end of a message, older logs may lack them
+fn read_stamp(tokens: &mut SplitWhitespace) -> FieldResult<robot_data::Stamp> {
+    let Some(token) = tokens.next() else {
+        return Ok(robot_data::Stamp::default());
+    };
+    let timestamp: f64 = token.parse().map_err(|_| FieldError {
+        field: "timestamp",
+        token: Some(token.to_string()),
+    })?;
+    let hostname: String = next_value(tokens, "hostname")?;
+    let logger_timestamp: f64 = next_value(tokens, "logger_timestamp")?;
+    Ok(robot_data::Stamp::new(
+        timestamp,
+        hostname,
+        logger_timestamp,
+    ))
+}
+
+/// Field of view of the old laser messages guessed from the number of beams
+fn guess_fov(num_beams: usize) -> f64 {
+    match num_beams {
+        181 | 361 => PI,
+        180 => PI / 180. * 179.,
+        360 => PI / 180. * 179.5,
+        401 => PI / 180. * 100.,
+        400 => PI / 180. * 99.75,
+        _ => PI,
+    }
+}
+
+/// Angular step of the old laser messages guessed from the number of beams
+fn guess_angular_step(num_beams: usize) -> f64 {
+    match num_beams {
+        180 | 181 => PI / 180.,
+        360 | 361 => PI / 360.,
+        400 | 401 => PI / 720.,
+        _ => guess_fov(num_beams) / (num_beams.max(2) - 1) as f64,
+    }
+}
+
+/// Stateful reader for the lines of a CARMEN log.
+///
+/// PARAM lines are remembered to recover the laser offsets of the old
+/// FLASER/RLASER messages which do not carry the laser pose themselves.
+#[derive(Default)]
+struct CarmenReader {
+    params: HashMap<String, f64>,
+}
+
+impl CarmenReader {
+    fn param(&self, name: &str, default: f64) -> f64 {
+        *self.params.get(name).unwrap_or(&default)
+    }
+
+    fn laser_offset(&self, mount: LaserMount) -> na::Isometry2<f64> {
+        let prefix = format!("robot_{}laser", mount.name());
+        let default_theta = match mount {
+            LaserMount::Front => 0.,
+            LaserMount::Rear => PI,
+        };
+        let x = self.param(&format!("{}_offset", prefix), 0.);
+        let y = self.param(&format!("{}_side_offset", prefix), 0.);
+        let theta = self.param(&format!("{}_angular_offset", prefix), default_theta);
+        na::Isometry2::new(na::Vector2::new(x, y), theta)
+    }
+
+    fn laser_max_range(&self, mount: LaserMount) -> f64 {
+        let name = format!("robot_{}_laser_max", mount.name());
+        self.param(&name, DEFAULT_LASER_MAX_RANGE)
+    }
+
+    fn read_line(&mut self, line: &str) -> FieldResult<Option<CarmenMessage>> {
+        let mut tokens = line.split_whitespace();
+        let Some(tag) = tokens.next() else {
+            return Ok(None);
+        };
+        let message = match tag {
+            "PARAM" => self.read_param(&mut tokens)?,
+            "ODOM" => read_odometry(&mut tokens)?,
+            "TRUEPOS" => read_truepos(&mut tokens)?,
+            "FLASER" => self.read_old_laser(&mut tokens, LaserMount::Front)?,
+            "RLASER" => self.read_old_laser(&mut tokens, LaserMount::Rear)?,
+            _ if tag.starts_with("ROBOTLASER") => {
+                CarmenMessage::RobotLaser(read_robotlaser(&mut tokens)?)
+            }
+            _ if tag.starts_with("RAWLASER") => match tag["RAWLASER".len()..].parse() {
+                Ok(laser_id) => self.read_rawlaser(&mut tokens, laser_id)?,
+                Err(_) => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(message))
     }
 
-    let num_remissions: i32 = tokens.next().unwrap().parse().unwrap();
-    // TODO(Rainer): Use advance_by later
-    for _ in 0..num_remissions {
-        tokens.next();
+    fn read_param(&mut self, tokens: &mut SplitWhitespace) -> FieldResult<CarmenMessage> {
+        let name: String = next_value(tokens, "param_name")?;
+        let value: String = next_value(tokens, "param_value")?;
+        if let Ok(v) = value.parse() {
+            self.params.insert(name.clone(), v);
+        }
+        Ok(CarmenMessage::Param(name, value))
     }
 
-    let laser_x: f64 = tokens.next().unwrap().parse().unwrap();
-    let laser_y: f64 = tokens.next().unwrap().parse().unwrap();
-    let laser_theta: f64 = tokens.next().unwrap().parse().unwrap();
-    let laser_pose_global = na::Isometry2::new(na::Vector2::new(laser_x, laser_y), laser_theta);
+    fn read_old_laser(
+        &self,
+        tokens: &mut SplitWhitespace,
+        mount: LaserMount,
+    ) -> FieldResult<CarmenMessage> {
+        let ranges = read_ranges(tokens)?;
+        let _laser_pose = read_pose(tokens, ["x", "y", "theta"])?;
+        let robot_pose_global = read_pose(tokens, ["odom_x", "odom_y", "odom_theta"])?;
+        let stamp = read_stamp(tokens)?;
 
-    let robot_x: f64 = tokens.next().unwrap().parse().unwrap();
-    let robot_y: f64 = tokens.next().unwrap().parse().unwrap();
-    let robot_theta: f64 = tokens.next().unwrap().parse().unwrap();
-    let robot_pose_global = na::Isometry2::new(na::Vector2::new(robot_x, robot_y), robot_theta);
+        let fov = guess_fov(ranges.len());
+        let laser_params = robot_data::LaserParameters::new(
+            self.laser_offset(mount),
+            -0.5 * fov,
+            guess_angular_step(ranges.len()),
+            self.laser_max_range(mount),
+        );
+        Ok(CarmenMessage::RobotLaser(robot_data::RobotLaser::new(
+            laser_params,
+            robot_pose_global,
+            ranges,
+            Vec::new(),
+            stamp,
+        )))
+    }
+
+    fn read_rawlaser(
+        &self,
+        tokens: &mut SplitWhitespace,
+        laser_id: u32,
+    ) -> FieldResult<CarmenMessage> {
+        let laser_pose = match laser_id {
+            1 => self.laser_offset(LaserMount::Front),
+            2 => self.laser_offset(LaserMount::Rear),
+            _ => na::Isometry2::identity(),
+        };
+        let (laser_params, ranges, remissions) = read_laser_config_and_ranges(tokens, laser_pose)?;
+        let stamp = read_stamp(tokens)?;
+        Ok(CarmenMessage::RawLaser(robot_data::RawLaser::new(
+            laser_id,
+            laser_params,
+            ranges,
+            remissions,
+            stamp,
+        )))
+    }
+}
+
+fn read_laser_config_and_ranges(
+    tokens: &mut SplitWhitespace,
+    laser_pose: na::Isometry2<f64>,
+) -> FieldResult<(robot_data::LaserParameters, Vec<f32>, Vec<f32>)> {
+    let _laser_type: i32 = next_value(tokens, "laser_type")?;
+    let angle: f64 = next_value(tokens, "start_angle")?;
+    let _fov: f64 = next_value(tokens, "field_of_view")?;
+    let angular_step: f64 = next_value(tokens, "angular_resolution")?;
+    let max_range: f64 = next_value(tokens, "maximum_range")?;
+    let _accuracy: f64 = next_value(tokens, "accuracy")?;
+    let _remission_mode: i32 = next_value(tokens, "remission_mode")?;
+
+    // parsing the beams
+    let ranges = read_ranges(tokens)?;
+
+    let remissions = read_values(tokens, "num_remissions", "remission")?;
+
+    let laser_params = robot_data::LaserParameters::new(laser_pose, angle, angular_step, max_range);
+    Ok((laser_params, ranges, remissions))
+}
+
+/// Read a ROBOTLASER message, the tag has to be consumed already
+pub fn read_robotlaser(tokens: &mut SplitWhitespace) -> FieldResult<robot_data::RobotLaser> {
+    let (mut laser_params, ranges, remissions) =
+        read_laser_config_and_ranges(tokens, na::Isometry2::identity())?;
+
+    let laser_pose_global =
+        read_pose(tokens, ["laser_pose_x", "laser_pose_y", "laser_pose_theta"])?;
+    let robot_pose_global =
+        read_pose(tokens, ["robot_pose_x", "robot_pose_y", "robot_pose_theta"])?;
+    for field in [
+        "laser_tv",
+        "laser_rv",
+        "forward_safety_dist",
+        "side_safety_dist",
+        "turn_axis",
+    ] {
+        let _value: f64 = next_value(tokens, field)?;
+    }
+    let stamp = read_stamp(tokens)?;
 
     // Relative laser pose and the parameters finally
-    let laser_pose_relative = robot_pose_global.inverse() * laser_pose_global;
-    let laser_params =
-        robot_data::LaserParameters::new(laser_pose_relative, angle, angular_step, max_range);
+    laser_params.laser_pose = robot_pose_global.inverse() * laser_pose_global;
 
-    Some(robot_data::RobotLaser::new(
+    Ok(robot_data::RobotLaser::new(
         laser_params,
         robot_pose_global,
         ranges,
+        remissions,
+        stamp,
     ))
 }
 
+fn read_odometry(tokens: &mut SplitWhitespace) -> FieldResult<CarmenMessage> {
+    let pose = read_pose(tokens, ["x", "y", "theta"])?;
+    let tv: f64 = next_value(tokens, "tv")?;
+    let rv: f64 = next_value(tokens, "rv")?;
+    let accel: f64 = next_value(tokens, "accel")?;
+    let stamp = read_stamp(tokens)?;
+    Ok(CarmenMessage::Odometry(robot_data::Odometry::new(
+        pose, tv, rv, accel, stamp,
+    )))
+}
+
+fn read_truepos(tokens: &mut SplitWhitespace) -> FieldResult<CarmenMessage> {
+    let true_pose = read_pose(tokens, ["true_x", "true_y", "true_theta"])?;
+    let odom_pose = read_pose(tokens, ["odom_x", "odom_y", "odom_theta"])?;
+    let stamp = read_stamp(tokens)?;
+    Ok(CarmenMessage::TruePos(robot_data::TruePos::new(
+        true_pose, odom_pose, stamp,
+    )))
+}
+
+impl CarmenFile {
+    /// Read the known messages of the log file one at a time
+    pub fn messages(
+        &self,
+    ) -> Result<impl Iterator<Item = Result<CarmenMessage, ParseError>>, ParseError> {
+        let mut reader = CarmenReader::default();
+        parser::read_lines(&self.filename, move |l| reader.read_line(l))
+    }
+
+    /// Parse all the known messages of the log file
+    pub fn parse_messages(&self, mode: ParseMode) -> Result<Parsed<CarmenMessage>, ParseError> {
+        parser::collect(self.messages()?, mode)
+    }
+
+    /// Ground truth trajectory given by the TRUEPOS messages, sorted by time
+    pub fn true_poses(&self, mode: ParseMode) -> Result<Parsed<TimedPose>, ParseError> {
+        let poses = self.messages()?.filter_map(|m| match m {
+            Ok(CarmenMessage::TruePos(p)) => Some(Ok(TimedPose {
+                timestamp: p.stamp.timestamp,
+                pose: p.true_pose,
+            })),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        });
+        let mut parsed = parser::collect(poses, mode)?;
+        parsed
+            .data
+            .sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+        Ok(parsed)
+    }
+}
+
 impl parser::Parser for CarmenFile {
-    fn parse(&self) -> Vec<robot_data::RobotLaser> {
-        match read_lines(&self.filename) {
-            Ok(lines) => lines
-                .map_while(Result::ok)
-                .filter_map(|l| read_robotlaser(&l))
-                .collect(),
-            Err(_) => Vec::new(),
-        }
+    fn scans(&self) -> Result<parser::Scans, ParseError> {
+        let scans = self.messages()?.filter_map(|m| match m {
+            Ok(CarmenMessage::RobotLaser(rl)) => Some(Ok(rl)),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        });
+        Ok(Box::new(scans))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flaser_with_param_offset() {
+        let mut reader = CarmenReader::default();
+        reader
+            .read_line("PARAM robot_frontlaser_offset 0.25 0.0 nohost 0.0")
+            .unwrap();
+        let msg = reader.read_line("FLASER 3 1.0 2.0 3.0 0 0 0 1.0 2.0 0.5 0.1 nohost 0.1");
+        let Ok(Some(CarmenMessage::RobotLaser(rl))) = msg else {
+            panic!("Expected a laser message");
+        };
+        assert_eq!(rl.ranges, vec![1.0, 2.0, 3.0]);
+        assert_eq!(rl.odom_pose.translation.vector, na::Vector2::new(1.0, 2.0));
+        assert_eq!(rl.laser_params.laser_pose.translation.x, 0.25);
+        assert_eq!(rl.laser_params.first_beam_theta, -0.5 * PI);
+        assert_eq!(rl.laser_params.angular_step, 0.5 * PI);
+        assert_eq!(rl.stamp, robot_data::Stamp::new(0.1, "nohost".into(), 0.1));
+    }
+
+    #[test]
+    fn robotlaser_remissions_and_stamp() {
+        let mut reader = CarmenReader::default();
+        let msg = reader.read_line(
+            "ROBOTLASER1 0 -1.57 3.14 1.57 20 0.01 0 3 1.0 2.0 3.0 3 0.1 0.2 0.3 \
+             1.1 2.0 0.0 1.0 2.0 0.0 0 0 0 0 0 12.5 robot 12.6",
+        );
+        let Ok(Some(CarmenMessage::RobotLaser(rl))) = msg else {
+            panic!("Expected a laser message");
+        };
+        assert_eq!(rl.ranges, vec![1.0, 2.0, 3.0]);
+        assert_eq!(rl.remissions, vec![0.1, 0.2, 0.3]);
+        assert_eq!(rl.timestamp(), 12.5);
+        assert_eq!(rl.stamp.hostname, "robot");
+        assert_eq!(rl.stamp.logger_timestamp, 12.6);
+    }
+
+    #[test]
+    fn malformed_line() {
+        let mut reader = CarmenReader::default();
+        let err = reader.read_line("ODOM 1.0 2.0 abc").err().unwrap();
+        assert_eq!(err.field, "theta");
+        assert_eq!(err.token.as_deref(), Some("abc"));
+        let err = reader.read_line("FLASER 3 1.0 2.0").err().unwrap();
+        assert_eq!(err.field, "range");
+        assert_eq!(err.token, None);
+        let err = reader.read_line("FLASER 99999999999999 1.0").err().unwrap();
+        assert_eq!(err.field, "range");
     }
 }
@@ -0,0 +1,131 @@
+extern crate nalgebra as na;
+
+use std::cell::RefCell;
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use super::parser::{self, next_value, read_pose, FieldResult, ParseError};
+use super::parser_carmen::read_robotlaser;
+use super::robot_data;
+
+/// Pose graph in the g2o or TORO format.
+///
+/// The laser data stored in the ROBOTLASER lines of a g2o file belongs to the
+/// vertex preceding it, other lines like comments or FIX may be in between.
+/// Those scans are returned with the pose of the vertex instead of the
+/// odometry pose of the original log. Plain TORO graphs do not contain laser
+/// data, thus the ROBOTLASER lines have to be added after their `VERTEX2`
+/// lines, a graph without any ROBOTLASER line is reported as error.
+pub struct G2oFile {
+    pub filename: PathBuf,
+}
+
+#[derive(Default)]
+struct G2oReader {
+    vertex_pose: Option<na::Isometry2<f64>>,
+    ///< a ROBOTLASER line has been read
+    has_laser: bool,
+}
+
+impl G2oReader {
+    fn read_line(&mut self, line: &str) -> FieldResult<Option<robot_data::RobotLaser>> {
+        let mut tokens = line.split_whitespace();
+        let Some(tag) = tokens.next() else {
+            return Ok(None);
+        };
+        match tag {
+            "VERTEX_SE2" | "VERTEX2" => {
+                self.vertex_pose = None;
+                let _id: i64 = next_value(&mut tokens, "id")?;
+                self.vertex_pose = Some(read_pose(&mut tokens, ["x", "y", "theta"])?);
+                Ok(None)
+            }
+            _ if tag.starts_with("ROBOTLASER") => {
+                self.has_laser = true;
+                let Some(vertex_pose) = self.vertex_pose else {
+                    return Ok(None);
+                };
+                let mut rl = read_robotlaser(&mut tokens)?;
+                rl.odom_pose = vertex_pose;
+                Ok(Some(rl))
+            }
+            _ if tag.starts_with("VERTEX") || tag.starts_with("EDGE") => {
+                // edges and other vertices end the data of the previous vertex
+                self.vertex_pose = None;
+                Ok(None)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Scans of the lines of a graph, a graph without any laser data ends with an error
+fn graph_scans<R: BufRead + 'static>(filename: PathBuf, lines: R) -> parser::Scans {
+    let reader = Rc::new(RefCell::new(G2oReader::default()));
+    let line_reader = reader.clone();
+    let scans = parser::read_lines_from(filename.clone(), lines, move |l| {
+        line_reader.borrow_mut().read_line(l)
+    });
+    // checked once all the lines have been read
+    let no_laser = std::iter::once_with(move || {
+        (!reader.borrow().has_laser).then_some(Err(ParseError::NoLaserData { filename }))
+    })
+    .flatten();
+    Box::new(scans.chain(no_laser))
+}
+
+impl parser::Parser for G2oFile {
+    fn scans(&self) -> Result<parser::Scans, ParseError> {
+        let lines = parser::open(&self.filename)?;
+        Ok(graph_scans(self.filename.clone(), lines))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn laser_uses_vertex_pose() {
+        let mut reader = G2oReader::default();
+        assert!(reader
+            .read_line("VERTEX_SE2 0 5.0 6.0 1.0")
+            .unwrap()
+            .is_none());
+        let rl = reader
+            .read_line(
+                "ROBOTLASER1 0 -1.57 3.14 1.57 20 0.01 0 3 1.0 2.0 3.0 0 \
+                 1.1 2.0 0.0 1.0 2.0 0.0 0 0 0 0 0 12.5 robot 12.6",
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(rl.odom_pose.translation.vector, na::Vector2::new(5.0, 6.0));
+        assert_eq!(rl.odom_pose.rotation.angle(), 1.0);
+        assert!((rl.laser_params.laser_pose.translation.x - 0.1).abs() < 1e-9);
+
+        reader.read_line("EDGE_SE2 0 1 1 0 0 1 0 0 1 0 1").unwrap();
+        let data = "ROBOTLASER1 0 -1.57 3.14 1.57 20 0.01 0 0 0 0 0 0 0 0 0 0 0 0 0 0 1 h 1";
+        assert!(reader.read_line(data).unwrap().is_none());
+
+        // other lines between the vertex and its laser data are ignored
+        reader.read_line("VERTEX2 1 1.0 2.0 0.0").unwrap();
+        reader.read_line("FIX 1").unwrap();
+        reader.read_line("# comment").unwrap();
+        let rl = reader.read_line(data).unwrap().unwrap();
+        assert_eq!(rl.odom_pose.translation.vector, na::Vector2::new(1.0, 2.0));
+    }
+
+    #[test]
+    fn toro_graph_without_laser() {
+        let mut reader = G2oReader::default();
+        for line in [
+            "VERTEX2 0 0 0 0",
+            "VERTEX2 1 1 0 0",
+            "EDGE2 0 1 1 0 0 1 0 1 1 0 0",
+        ] {
+            assert!(reader.read_line(line).unwrap().is_none());
+        }
+        assert!(!reader.has_laser);
+    }
+}
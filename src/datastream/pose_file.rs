@@ -0,0 +1,119 @@
+extern crate nalgebra as na;
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use super::parser::{self, next_value, read_pose, FieldResult, ParseError, ParseMode, Parsed};
+use super::robot_data::RobotLaser;
+
+/// Trajectory file with one `timestamp x y theta` pose per line.
+///
+/// The values are separated by whitespace (TUM style) or commas (CSV). Empty
+/// lines, comments starting with `#` and a header line are ignored.
+pub struct PoseFile {
+    pub filename: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TimedPose {
+    pub timestamp: f64,
+    pub pose: na::Isometry2<f64>,
+}
+
+/// How to find the pose of a scan within a trajectory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PoseAssociation {
+    /// Interpolate the pose at the timestamp of the scan
+    #[default]
+    Timestamp,
+    /// Take the pose with the same index as the scan
+    Index,
+}
+
+fn read_timed_pose(line: &str) -> FieldResult<Option<TimedPose>> {
+    let line = line.replace(',', " ");
+    let mut tokens = line.split_whitespace();
+    match tokens.clone().next() {
+        None => return Ok(None),
+        Some(t) if t.starts_with('#') || t.starts_with(char::is_alphabetic) => return Ok(None),
+        Some(_) => {}
+    }
+    let timestamp: f64 = next_value(&mut tokens, "timestamp")?;
+    let pose = read_pose(&mut tokens, ["x", "y", "theta"])?;
+    Ok(Some(TimedPose { timestamp, pose }))
+}
+
+impl PoseFile {
+    pub fn parse(&self, mode: ParseMode) -> Result<Parsed<TimedPose>, ParseError> {
+        let mut parsed = parser::parse_lines(&self.filename, mode, read_timed_pose)?;
+        parsed
+            .data
+            .sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+        Ok(parsed)
+    }
+}
+
+/// Interpolate the pose at `timestamp` within a trajectory sorted by time
+pub fn interpolate_pose(poses: &[TimedPose], timestamp: f64) -> Option<na::Isometry2<f64>> {
+    let idx = poses.partition_point(|p| p.timestamp < timestamp);
+    let next = poses.get(idx)?;
+    if next.timestamp == timestamp {
+        return Some(next.pose);
+    }
+    let prev = poses.get(idx.checked_sub(1)?)?;
+    let t = (timestamp - prev.timestamp) / (next.timestamp - prev.timestamp);
+    Some(prev.pose.lerp_slerp(&next.pose, t))
+}
+
+/// Replace the odometry pose of the scan with the given index by the pose of
+/// a trajectory, `None` if the trajectory does not provide a pose
+pub fn replace_pose(
+    mut rl: RobotLaser,
+    index: usize,
+    poses: &[TimedPose],
+    association: PoseAssociation,
+) -> Option<RobotLaser> {
+    rl.odom_pose = match association {
+        PoseAssociation::Timestamp => interpolate_pose(poses, rl.timestamp()),
+        PoseAssociation::Index => poses.get(index).map(|p| p.pose),
+    }?;
+    Some(rl)
+}
+
+/// Replace the odometry poses of the scans by the poses of a trajectory.
+///
+/// Scans for which the trajectory does not provide a pose are dropped.
+pub fn replace_poses(
+    scans: Vec<RobotLaser>,
+    poses: &[TimedPose],
+    association: PoseAssociation,
+) -> Vec<RobotLaser> {
+    scans
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, rl)| replace_pose(rl, i, poses, association))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolation() {
+        let poses: Vec<TimedPose> = ["1.0,0,0,0", "# comment", "2.0 2.0 4.0 1.0"]
+            .iter()
+            .filter_map(|l| read_timed_pose(l).unwrap())
+            .collect();
+        assert_eq!(poses.len(), 2);
+        let pose = interpolate_pose(&poses, 1.5).unwrap();
+        assert_eq!(pose.translation.vector, na::Vector2::new(1.0, 2.0));
+        assert!((pose.rotation.angle() - 0.5).abs() < 1e-9);
+        assert_eq!(interpolate_pose(&poses, 2.0).unwrap(), poses[1].pose);
+        assert!(interpolate_pose(&poses, 0.5).is_none());
+        assert!(interpolate_pose(&poses, 2.5).is_none());
+        assert!(read_timed_pose("timestamp,x,y,theta").unwrap().is_none());
+    }
+}
@@ -1,5 +1,6 @@
 extern crate nalgebra as na;
 
+#[derive(Debug, Clone)]
 pub struct LaserParameters {
     pub laser_pose: na::Isometry2<f64>,
     pub first_beam_theta: f64,
@@ -32,25 +33,129 @@ impl LaserParameters {
     }
 }
 
+/// Time and origin of a message as written by the logger
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stamp {
+    /// time the message was created on the robot
+    pub timestamp: f64,
+    /// host which created the message
+    pub hostname: String,
+    /// time the message was received by the logger
+    pub logger_timestamp: f64,
+}
+
+impl Stamp {
+    pub fn new(timestamp: f64, hostname: String, logger_timestamp: f64) -> Self {
+        Self {
+            timestamp,
+            hostname,
+            logger_timestamp,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct RobotLaser {
     pub laser_params: LaserParameters,
     pub odom_pose: na::Isometry2<f64>,
     pub ranges: Vec<f32>,
+    pub remissions: Vec<f32>,
+    pub stamp: Stamp,
 }
 impl RobotLaser {
     pub fn new(
         laser_params: LaserParameters,
         odom_pose: na::Isometry2<f64>,
         ranges: Vec<f32>,
+        remissions: Vec<f32>,
+        stamp: Stamp,
     ) -> Self {
         Self {
             laser_params,
             odom_pose,
             ranges,
+            remissions,
+            stamp,
         }
     }
 
     pub fn laser_pose(&self) -> na::Isometry2<f64> {
         self.odom_pose * self.laser_params.laser_pose
     }
+
+    pub fn timestamp(&self) -> f64 {
+        self.stamp.timestamp
+    }
+}
+
+/// Scan for tests with the laser at the center of the robot, beams every 0.5
+/// rad starting at -1 rad and a max range of 20 m
+#[cfg(test)]
+pub fn test_scan(pose: na::Isometry2<f64>, ranges: Vec<f32>) -> RobotLaser {
+    RobotLaser::new(
+        LaserParameters::new(na::Isometry2::identity(), -1., 0.5, 20.),
+        pose,
+        ranges,
+        Vec::new(),
+        Stamp::default(),
+    )
+}
+
+pub struct RawLaser {
+    pub laser_id: u32,
+    pub laser_params: LaserParameters,
+    pub ranges: Vec<f32>,
+    pub remissions: Vec<f32>,
+    pub stamp: Stamp,
+}
+impl RawLaser {
+    pub fn new(
+        laser_id: u32,
+        laser_params: LaserParameters,
+        ranges: Vec<f32>,
+        remissions: Vec<f32>,
+        stamp: Stamp,
+    ) -> Self {
+        Self {
+            laser_id,
+            laser_params,
+            ranges,
+            remissions,
+            stamp,
+        }
+    }
+}
+
+pub struct Odometry {
+    pub pose: na::Isometry2<f64>,
+    pub tv: f64,
+    pub rv: f64,
+    pub accel: f64,
+    pub stamp: Stamp,
+}
+impl Odometry {
+    pub fn new(pose: na::Isometry2<f64>, tv: f64, rv: f64, accel: f64, stamp: Stamp) -> Self {
+        Self {
+            pose,
+            tv,
+            rv,
+            accel,
+            stamp,
+        }
+    }
+}
+
+pub struct TruePos {
+    pub true_pose: na::Isometry2<f64>,
+    pub odom_pose: na::Isometry2<f64>,
+    pub stamp: Stamp,
+}
+impl TruePos {
+    pub fn new(true_pose: na::Isometry2<f64>, odom_pose: na::Isometry2<f64>, stamp: Stamp) -> Self {
+        Self {
+            true_pose,
+            odom_pose,
+            stamp,
+        }
+    }
 }
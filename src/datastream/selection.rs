@@ -0,0 +1,193 @@
+extern crate nalgebra as na;
+
+use serde::{Deserialize, Serialize};
+
+use super::robot_data::RobotLaser;
+
+/// Scans of a log which go into the map, selected by their index within the
+/// log, by their timestamp and by the motion of the robot. The default
+/// selects all scans.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScanSelection {
+    ///< index of the first scan
+    pub start: usize,
+    ///< index after the last scan, all scans if not given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<usize>,
+    ///< only every n-th scan counted from `start`
+    pub every: usize,
+    ///< scans with an earlier timestamp are dropped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<f64>,
+    ///< scans with a later timestamp are dropped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<f64>,
+    ///< keep a scan after the robot moved this distance in meter since the last kept scan
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_distance: Option<f64>,
+    ///< keep a scan after the robot turned this angle in degrees since the last kept scan
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_angle: Option<f64>,
+}
+
+impl Default for ScanSelection {
+    fn default() -> Self {
+        Self {
+            start: 0,
+            end: None,
+            every: 1,
+            start_time: None,
+            end_time: None,
+            min_distance: None,
+            min_angle: None,
+        }
+    }
+}
+
+impl ScanSelection {
+    /// Whether the scan with the given index within the log is selected
+    pub fn contains(&self, index: usize, scan: &RobotLaser) -> bool {
+        let timestamp = scan.timestamp();
+        index >= self.start
+            && self.end.is_none_or(|end| index < end)
+            && (index - self.start).is_multiple_of(self.every.max(1))
+            && self.start_time.is_none_or(|t| timestamp >= t)
+            && self.end_time.is_none_or(|t| timestamp <= t)
+    }
+
+    /// Whether no scan after the given index can be selected
+    pub fn is_past(&self, index: usize) -> bool {
+        self.end.is_some_and(|end| index >= end)
+    }
+
+    /// Keep the scans selected by their index and timestamp together with
+    /// their index within the log, the stream is not read beyond the end
+    /// index. Keyframes are not filtered as they depend on the final poses.
+    pub fn apply_enumerated<I>(self, scans: I) -> impl Iterator<Item = (usize, RobotLaser)>
+    where
+        I: IntoIterator<Item = RobotLaser>,
+    {
+        scans
+            .into_iter()
+            .enumerate()
+            .take_while(move |(i, _)| !self.is_past(*i))
+            .filter(move |(i, rl)| self.contains(*i, rl))
+    }
+
+    /// Keep the selected scans of a stream, the stream is not read beyond the
+    /// end index
+    pub fn apply<I>(self, scans: I) -> impl Iterator<Item = RobotLaser>
+    where
+        I: IntoIterator<Item = RobotLaser>,
+    {
+        let mut keyframes = KeyframeFilter::new(&self);
+        self.apply_enumerated(scans)
+            .map(|(_, rl)| rl)
+            .filter(move |rl| keyframes.accept(&rl.odom_pose))
+    }
+}
+
+/// Keeps a scan only after the robot moved or turned more than the thresholds
+/// of a selection since the last kept scan. Without thresholds all scans are
+/// kept.
+#[derive(Debug, Clone)]
+pub struct KeyframeFilter {
+    min_distance: Option<f64>,
+    min_angle: Option<f64>,
+    last: Option<na::Isometry2<f64>>,
+}
+
+impl KeyframeFilter {
+    pub fn new(selection: &ScanSelection) -> Self {
+        Self {
+            min_distance: selection.min_distance,
+            min_angle: selection.min_angle.map(f64::to_radians),
+            last: None,
+        }
+    }
+
+    /// Whether the scan at `pose` is kept, the first scan always is
+    pub fn accept(&mut self, pose: &na::Isometry2<f64>) -> bool {
+        if let Some(last) = self.last {
+            let motion = last.inverse() * pose;
+            let moved = self
+                .min_distance
+                .is_some_and(|d| motion.translation.vector.norm() > d);
+            let turned = self
+                .min_angle
+                .is_some_and(|a| motion.rotation.angle().abs() > a);
+            let filtered = self.min_distance.is_some() || self.min_angle.is_some();
+            if filtered && !moved && !turned {
+                return false;
+            }
+        }
+        self.last = Some(*pose);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datastream::robot_data::{test_scan, Stamp};
+
+    #[test]
+    fn select_by_index_and_time() {
+        let scans: Vec<RobotLaser> = (0..10)
+            .map(|i| RobotLaser {
+                stamp: Stamp {
+                    timestamp: 100. + i as f64,
+                    ..Default::default()
+                },
+                ..test_scan(nalgebra::Isometry2::identity(), vec![1.])
+            })
+            .collect();
+        let selected = |selection: ScanSelection| -> Vec<f64> {
+            selection
+                .apply(scans.iter().cloned())
+                .map(|rl| rl.timestamp() - 100.)
+                .collect()
+        };
+        assert_eq!(selected(ScanSelection::default()).len(), 10);
+        let range = ScanSelection {
+            start: 2,
+            end: Some(9),
+            every: 3,
+            ..Default::default()
+        };
+        assert_eq!(selected(range), [2., 5., 8.]);
+        let window = ScanSelection {
+            start_time: Some(103.5),
+            end_time: Some(106.),
+            ..Default::default()
+        };
+        assert_eq!(selected(window), [4., 5., 6.]);
+    }
+
+    #[test]
+    fn keyframes_after_motion() {
+        let selection = ScanSelection {
+            min_distance: Some(0.5),
+            min_angle: Some(10.),
+            ..Default::default()
+        };
+        let mut filter = KeyframeFilter::new(&selection);
+        let pose = |x: f64, deg: f64| na::Isometry2::new(na::Vector2::new(x, 0.), deg.to_radians());
+        let kept: Vec<bool> = [
+            pose(0., 0.),
+            pose(0.3, 0.),
+            pose(0.6, 0.),
+            pose(0.6, 5.),
+            pose(0.6, 15.),
+            pose(0.6, 15.),
+        ]
+        .iter()
+        .map(|p| filter.accept(p))
+        .collect();
+        assert_eq!(kept, [true, false, true, false, true, false]);
+
+        let mut all = KeyframeFilter::new(&ScanSelection::default());
+        assert!(all.accept(&pose(0., 0.)) && all.accept(&pose(0., 0.)));
+    }
+}
@@ -1 +1,8 @@
+pub mod animation;
+pub mod annotation;
+pub mod drawing_parameter;
 pub mod map_drawer;
+pub mod map_server;
+pub mod overlay;
+pub mod text;
+pub mod vector;
@@ -0,0 +1,293 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::error::{ParameterError, ParameterErrorKind};
+use image::{Delay, Frame, ImageError, ImageResult, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+/// Options for animating the scans
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnimationParameter {
+    ///< index of the first scan
+    pub start: usize,
+    ///< index after the last scan, all scans if not given
+    pub end: Option<usize>,
+    ///< only animate every n-th scan
+    pub step: usize,
+    ///< frame rate of animated outputs
+    pub fps: u32,
+    ///< draw the path of the robot up to the current scan
+    pub draw_path: bool,
+}
+
+impl Default for AnimationParameter {
+    fn default() -> Self {
+        Self {
+            start: 0,
+            end: None,
+            step: 1,
+            fps: 10,
+            draw_path: false,
+        }
+    }
+}
+
+impl AnimationParameter {
+    /// Check that the frame rate is positive
+    pub fn validate(&self) -> ImageResult<()> {
+        check_fps(self.fps)
+    }
+}
+
+fn check_fps(fps: u32) -> ImageResult<()> {
+    if fps == 0 {
+        return Err(ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::Generic("the frame rate has to be positive".to_string()),
+        )));
+    }
+    Ok(())
+}
+
+/// Output of an animation receiving the frames as RGBA data
+pub trait FrameSink {
+    fn add_frame(&mut self, rgba: &[u8]) -> ImageResult<()>;
+    fn finish(self: Box<Self>) -> ImageResult<()>;
+    /// Name of the file receiving the next frame if written to separate files
+    fn frame_name(&self) -> Option<String>;
+}
+
+/// Select the output by the filename.
+///
+/// `-` writes a Y4M stream to stdout, the extensions `gif`, `png` and `y4m`
+/// write a single animated file. Any other path is a folder receiving one PNG
+/// image per frame. The frame rate `fps` has to be positive.
+pub fn create_sink(
+    output: &Path,
+    width: u32,
+    height: u32,
+    num_frames: usize,
+    fps: u32,
+) -> ImageResult<Box<dyn FrameSink>> {
+    check_fps(fps)?;
+    if output == Path::new("-") {
+        let writer = BufWriter::new(io::stdout().lock());
+        return Ok(Box::new(Y4mWriter::new(writer, width, height, fps)?));
+    }
+    let sink: Box<dyn FrameSink> = match output.extension().and_then(|e| e.to_str()) {
+        Some("gif") => Box::new(GifSink::new(output, width, height, fps)?),
+        Some("png") => Box::new(ApngSink::new(output, width, height, num_frames, fps)?),
+        Some("y4m") => {
+            let writer = BufWriter::new(File::create(output)?);
+            Box::new(Y4mWriter::new(writer, width, height, fps)?)
+        }
+        _ => Box::new(PngSequence {
+            folder: output.to_path_buf(),
+            width,
+            height,
+            digits: num_frames.max(1).ilog10() as usize + 1,
+            count: 0,
+        }),
+    };
+    Ok(sink)
+}
+
+fn encoding_error(e: impl std::fmt::Display) -> ImageError {
+    ImageError::IoError(io::Error::other(e.to_string()))
+}
+
+struct PngSequence {
+    folder: PathBuf,
+    width: u32,
+    height: u32,
+    digits: usize,
+    count: usize,
+}
+
+impl PngSequence {
+    fn filename(&self) -> PathBuf {
+        let image_name = format!("image_{:0width$}.png", self.count, width = self.digits);
+        [&self.folder, &PathBuf::from(image_name)].iter().collect()
+    }
+}
+
+impl FrameSink for PngSequence {
+    fn add_frame(&mut self, rgba: &[u8]) -> ImageResult<()> {
+        image::save_buffer(
+            self.filename(),
+            rgba,
+            self.width,
+            self.height,
+            image::ExtendedColorType::Rgba8,
+        )?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> ImageResult<()> {
+        Ok(())
+    }
+
+    fn frame_name(&self) -> Option<String> {
+        Some(self.filename().to_string_lossy().into_owned())
+    }
+}
+
+struct GifSink {
+    encoder: GifEncoder<BufWriter<File>>,
+    width: u32,
+    height: u32,
+    delay: Delay,
+}
+
+impl GifSink {
+    fn new(filename: &Path, width: u32, height: u32, fps: u32) -> ImageResult<Self> {
+        let mut encoder = GifEncoder::new_with_speed(BufWriter::new(File::create(filename)?), 10);
+        encoder.set_repeat(Repeat::Infinite)?;
+        Ok(Self {
+            encoder,
+            width,
+            height,
+            delay: Delay::from_numer_denom_ms(1000, fps),
+        })
+    }
+}
+
+impl FrameSink for GifSink {
+    fn add_frame(&mut self, rgba: &[u8]) -> ImageResult<()> {
+        let buffer = RgbaImage::from_raw(self.width, self.height, rgba.to_vec())
+            .ok_or_else(|| encoding_error("frame size does not match the animation"))?;
+        self.encoder
+            .encode_frame(Frame::from_parts(buffer, 0, 0, self.delay))
+    }
+
+    fn finish(self: Box<Self>) -> ImageResult<()> {
+        Ok(())
+    }
+
+    fn frame_name(&self) -> Option<String> {
+        None
+    }
+}
+
+struct ApngSink {
+    writer: png::Writer<BufWriter<File>>,
+}
+
+impl ApngSink {
+    fn new(
+        filename: &Path,
+        width: u32,
+        height: u32,
+        num_frames: usize,
+        fps: u32,
+    ) -> ImageResult<Self> {
+        let mut encoder = png::Encoder::new(BufWriter::new(File::create(filename)?), width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .set_animated(num_frames.max(1) as u32, 0)
+            .map_err(encoding_error)?;
+        encoder
+            .set_frame_delay(1, fps.min(u16::MAX as u32) as u16)
+            .map_err(encoding_error)?;
+        let writer = encoder.write_header().map_err(encoding_error)?;
+        Ok(Self { writer })
+    }
+}
+
+impl FrameSink for ApngSink {
+    fn add_frame(&mut self, rgba: &[u8]) -> ImageResult<()> {
+        self.writer.write_image_data(rgba).map_err(encoding_error)
+    }
+
+    fn finish(self: Box<Self>) -> ImageResult<()> {
+        self.writer.finish().map_err(encoding_error)
+    }
+
+    fn frame_name(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Uncompressed YUV 4:4:4 stream which can be piped into a video encoder
+struct Y4mWriter<W: Write> {
+    writer: W,
+    frame: Vec<u8>,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    fn new(mut writer: W, width: u32, height: u32, fps: u32) -> ImageResult<Self> {
+        writeln!(writer, "YUV4MPEG2 W{width} H{height} F{fps}:1 Ip A1:1 C444")?;
+        Ok(Self {
+            writer,
+            frame: Vec::new(),
+        })
+    }
+}
+
+/// Convert RGB into the limited range YCbCr of BT.601
+fn rgb_to_ycbcr(r: u8, g: u8, b: u8) -> [u8; 3] {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    let y = 16. + (65.738 * r + 129.057 * g + 25.064 * b) / 256.;
+    let cb = 128. + (-37.945 * r - 74.494 * g + 112.439 * b) / 256.;
+    let cr = 128. + (112.439 * r - 94.154 * g - 18.285 * b) / 256.;
+    [y, cb, cr].map(|c| c.round().clamp(0., 255.) as u8)
+}
+
+impl<W: Write> FrameSink for Y4mWriter<W> {
+    fn add_frame(&mut self, rgba: &[u8]) -> ImageResult<()> {
+        let num_pixels = rgba.len() / 4;
+        self.frame.resize(3 * num_pixels, 0);
+        for (i, p) in rgba.chunks_exact(4).enumerate() {
+            let [y, cb, cr] = rgb_to_ycbcr(p[0], p[1], p[2]);
+            self.frame[i] = y;
+            self.frame[num_pixels + i] = cb;
+            self.frame[2 * num_pixels + i] = cr;
+        }
+        self.writer.write_all(b"FRAME\n")?;
+        self.writer.write_all(&self.frame)?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> ImageResult<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn frame_name(&self) -> Option<String> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn y4m_stream() {
+        let mut data = Vec::new();
+        let mut writer = Box::new(Y4mWriter::new(&mut data, 2, 1, 25).unwrap());
+        writer
+            .add_frame(&[255, 255, 255, 255, 0, 0, 0, 255])
+            .unwrap();
+        writer.finish().unwrap();
+        let header = b"YUV4MPEG2 W2 H1 F25:1 Ip A1:1 C444\nFRAME\n";
+        assert_eq!(&data[..header.len()], header);
+        assert_eq!(&data[header.len()..], &[235, 16, 128, 128, 128, 128]);
+    }
+
+    #[test]
+    fn reject_zero_fps() {
+        // fails before the file is created
+        assert!(create_sink(Path::new("zero_fps.gif"), 2, 1, 1, 0).is_err());
+        assert!(AnimationParameter {
+            fps: 0,
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+}
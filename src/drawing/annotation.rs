@@ -0,0 +1,188 @@
+extern crate nalgebra as na;
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::map_drawer::{Label, Layer, MapDrawer};
+use crate::config::{self, ConfigError};
+
+/// Point of interest drawn as a dot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Marker {
+    ///< world coordinates of the marker
+    pub position: [f64; 2],
+    ///< text drawn next to the marker
+    #[serde(default)]
+    pub label: Option<String>,
+    ///< RGBA color, the annotation style if not given
+    #[serde(default)]
+    pub color: Option<[u8; 4]>,
+    ///< diameter of the dot in pixels [default: 3 times the width of the annotation style]
+    #[serde(default)]
+    pub size: Option<f32>,
+}
+
+/// Line through several points, closed for a polygon
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Shape {
+    ///< world coordinates of the corners
+    pub points: Vec<[f64; 2]>,
+    ///< text drawn at the first corner
+    #[serde(default)]
+    pub label: Option<String>,
+    ///< RGBA color, the annotation style if not given
+    #[serde(default)]
+    pub color: Option<[u8; 4]>,
+    ///< width of the line in pixels, the annotation style if not given
+    #[serde(default)]
+    pub width: Option<f32>,
+}
+
+/// Annotations of a map given in the world coordinates of the logfile, i.e.,
+/// the offset of the map is applied to them as to the path.
+///
+/// ```toml
+/// [[markers]]
+/// position = [2.5, -1.0]
+/// label = "docking station"
+///
+/// [[polygons]]
+/// points = [[0, 0], [1, 0], [1, 1]]
+/// label = "failure"
+/// color = [255, 0, 0, 255]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Annotations {
+    pub markers: Vec<Marker>,
+    pub polylines: Vec<Shape>,
+    pub polygons: Vec<Shape>,
+}
+
+impl Annotations {
+    /// Read the annotations from a TOML or YAML file
+    pub fn load(filename: &Path) -> Result<Self, ConfigError> {
+        config::load_file(filename)
+    }
+}
+
+impl MapDrawer {
+    /// World coordinates of a point of the logfile
+    fn apply_offset(&self, p: [f64; 2]) -> [f64; 2] {
+        let p = self.parameter.offset * na::Point2::new(p[0], p[1]);
+        [p.x, p.y]
+    }
+
+    /// Label moved by a distance given in pixels
+    fn label_at(&self, position: [f64; 2], shift: [f32; 2], text: &Option<String>) -> Vec<Label> {
+        let res = self.parameter.resolution;
+        text.iter()
+            .map(|text| Label {
+                position: [
+                    position[0] + (shift[0] as f64) * res,
+                    position[1] + (shift[1] as f64) * res,
+                ],
+                text: text.clone(),
+            })
+            .collect()
+    }
+
+    /// Draw the annotations in `self.annotations`, their labels are drawn in
+    /// the text size of the overlays
+    pub fn draw_annotations(&mut self) {
+        let style = self.drawing.annotation;
+        let width = style.width.unwrap_or(2.);
+        let text_size = self.drawing.text_size;
+        let mut layers = Vec::new();
+        for marker in self.annotations.markers.iter() {
+            let color = marker.color.unwrap_or(style.color);
+            let size = marker.size.unwrap_or(3. * width);
+            let position = self.apply_offset(marker.position);
+            let shift = size / 2. + 2.;
+            layers.push(Layer {
+                polylines: Vec::new(),
+                points: vec![position],
+                labels: Vec::new(),
+                color,
+                width: size,
+            });
+            layers.push(Layer {
+                polylines: Vec::new(),
+                points: Vec::new(),
+                labels: self.label_at(position, [shift, shift], &marker.label),
+                color,
+                width: text_size,
+            });
+        }
+        let shapes = self.annotations.polylines.iter().map(|s| (s, false));
+        let polygons = self.annotations.polygons.iter().map(|s| (s, true));
+        for (shape, closed) in shapes.chain(polygons) {
+            let Some(first) = shape.points.first() else {
+                continue;
+            };
+            let color = shape.color.unwrap_or(style.color);
+            let mut polyline: Vec<[f64; 2]> =
+                shape.points.iter().map(|p| self.apply_offset(*p)).collect();
+            if closed {
+                polyline.push(polyline[0]);
+            }
+            let position = self.apply_offset(*first);
+            layers.push(Layer {
+                polylines: vec![polyline],
+                points: Vec::new(),
+                labels: Vec::new(),
+                color,
+                width: shape.width.unwrap_or(width),
+            });
+            layers.push(Layer {
+                polylines: Vec::new(),
+                points: Vec::new(),
+                labels: self.label_at(position, [3., 3.], &shape.label),
+                color,
+                width: text_size,
+            });
+        }
+        for layer in layers {
+            self.draw_layer(layer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::map_creator_parameter::MapCreatorParameter;
+
+    #[test]
+    fn draw_annotations_with_offset() {
+        let annotations: Annotations = toml::from_str(
+            "[[markers]]\nposition = [1.0, 0.0]\nlabel = \"dock\"\n\n\
+             [[polygons]]\npoints = [[0, 0], [1, 0], [1, 1]]\ncolor = [255, 0, 0, 255]\n",
+        )
+        .unwrap();
+        assert!(
+            toml::from_str::<Annotations>("[[markers]]\nposition = [0, 0]\nlable = \"a\"\n")
+                .is_err()
+        );
+
+        let parameter = MapCreatorParameter {
+            offset: na::Isometry2::new(na::Vector2::new(0.5, 0.5), 0.),
+            ..Default::default()
+        };
+        let pixmap = tiny_skia::Pixmap::new(30, 30).unwrap();
+        let mut drawer = MapDrawer::new(parameter, [0., 0.], pixmap);
+        drawer.annotations = annotations;
+        drawer.draw_annotations();
+
+        let layers = drawer.layers();
+        assert_eq!(layers.len(), 3);
+        assert_eq!(layers[0].points, [[1.5, 0.5]]);
+        assert_eq!(layers[1].labels[0].text, "dock");
+        assert_eq!(layers[2].polylines[0].len(), 4);
+        assert_eq!(layers[2].polylines[0][3], [0.5, 0.5]);
+        assert_eq!(layers[2].color, [255, 0, 0, 255]);
+    }
+}
@@ -0,0 +1,324 @@
+use serde::{Deserialize, Serialize};
+
+/// Predefined set of colors for the map and the overlays
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    /// Blue unknown space as known from the CARMEN tools
+    #[default]
+    Classic,
+    /// Shades of gray which survive black and white printing
+    Grayscale,
+    /// Light obstacles on a dark background
+    Dark,
+    /// Colors of the Okabe-Ito palette which are distinguishable with color blindness
+    ColorBlind,
+}
+
+/// How to draw a scan
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScanMode {
+    /// Lines from the laser to the endpoints of the beams
+    #[default]
+    Beams,
+    /// Dots at the endpoints of the beams
+    Points,
+}
+
+/// Attribute of a beam which selects its color from the color map
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScanColoring {
+    /// All beams in the color of the scan style
+    #[default]
+    Uniform,
+    /// Index of the beam within the scan
+    BeamIndex,
+    /// Measured range relative to the max usable range
+    Range,
+    /// Remission relative to the largest remission of the scan
+    Remission,
+    /// Time of the scan within the trajectory
+    Time,
+}
+
+/// Viridis color map for values between 0 and 1
+pub fn colormap(value: f32) -> [u8; 3] {
+    const COLORS: [[f32; 3]; 5] = [
+        [68., 1., 84.],
+        [59., 82., 139.],
+        [33., 145., 140.],
+        [94., 201., 98.],
+        [253., 231., 37.],
+    ];
+    let x = value.clamp(0., 1.) * (COLORS.len() - 1) as f32;
+    let i = (x as usize).min(COLORS.len() - 2);
+    let t = x - i as f32;
+    let mut color = [0; 3];
+    for (c, (a, b)) in color
+        .iter_mut()
+        .zip(COLORS[i].iter().zip(COLORS[i + 1].iter()))
+    {
+        *c = (a + (b - a) * t).round() as u8;
+    }
+    color
+}
+
+/// Color and stroke of a layer drawn on top of the map
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LayerStyle {
+    ///< RGBA color, the alpha value sets the opacity of the layer
+    pub color: [u8; 4],
+    ///< width of the stroke in pixels, the default width of the layer if not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<f32>,
+}
+
+impl LayerStyle {
+    pub fn new(color: [u8; 4]) -> Self {
+        Self { color, width: None }
+    }
+}
+
+/// Colors of the map and the style of the overlays.
+///
+/// A config starts from the colors of its `theme`, the other entries given
+/// in the config replace those of the theme.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(from = "DrawingSettings")]
+pub struct DrawingParameter {
+    ///< theme the colors are based on
+    pub theme: Theme,
+    ///< color of cells which are certainly free
+    pub free_color: [u8; 4],
+    ///< color of cells which are certainly occupied
+    pub occupied_color: [u8; 4],
+    ///< color of cells which have not been observed
+    pub unknown_color: [u8; 4],
+    ///< exponent applied to the occupancy, below 1 darkens rarely hit cells
+    pub gamma: f32,
+    ///< path of the robot, its default width is the path width of the map
+    pub path: LayerStyle,
+    ///< reference path of a trajectory evaluation, as wide as the path by default
+    pub reference_path: LayerStyle,
+    ///< beams of highlighted and animated scans, one pixel wide by default
+    pub scan: LayerStyle,
+    ///< draw the scans as beams or as endpoints
+    pub scan_mode: ScanMode,
+    ///< attribute selecting the color of a beam
+    pub color_by: ScanColoring,
+    ///< diameter of the endpoints in pixels
+    pub point_size: f32,
+    ///< draw the readings at the max usable range, cropped to it
+    pub show_max_range: bool,
+    ///< readings at the max usable range
+    pub max_range: LayerStyle,
+    ///< markers and lines of the annotations, 2 pixels wide by default
+    pub annotation: LayerStyle,
+    ///< scale bar, axes, legend and the text of the overlays
+    pub overlay: LayerStyle,
+    ///< lines of the coordinate grid
+    pub grid: LayerStyle,
+    ///< height of the text of the overlays in pixels
+    pub text_size: f32,
+}
+
+impl Default for DrawingParameter {
+    fn default() -> Self {
+        Self::from_theme(Theme::default())
+    }
+}
+
+impl DrawingParameter {
+    pub fn from_theme(theme: Theme) -> Self {
+        let (free, occupied, unknown, path, scan, max_range) = match theme {
+            Theme::Classic => (
+                [255, 255, 255, 255],
+                [0, 0, 0, 255],
+                [140, 170, 238, 255],
+                [231, 130, 132, 255],
+                [242, 213, 207, 100],
+                [180, 180, 180, 100],
+            ),
+            Theme::Grayscale => (
+                [255, 255, 255, 255],
+                [0, 0, 0, 255],
+                [205, 205, 205, 255],
+                [90, 90, 90, 255],
+                [140, 140, 140, 110],
+                [190, 190, 190, 110],
+            ),
+            Theme::Dark => (
+                [40, 42, 54, 255],
+                [235, 235, 235, 255],
+                [18, 18, 24, 255],
+                [255, 121, 198, 255],
+                [139, 233, 253, 110],
+                [98, 114, 164, 110],
+            ),
+            Theme::ColorBlind => (
+                [255, 255, 255, 255],
+                [0, 0, 0, 255],
+                [86, 180, 233, 255],
+                [213, 94, 0, 255],
+                [204, 121, 167, 120],
+                [0, 158, 115, 120],
+            ),
+        };
+        let (overlay, grid) = match theme {
+            Theme::Dark => ([235, 235, 235, 255], [235, 235, 235, 60]),
+            _ => ([0, 0, 0, 255], [0, 0, 0, 50]),
+        };
+        let reference_path = match theme {
+            Theme::Classic => [0, 90, 181, 255],
+            Theme::Grayscale => [0, 0, 0, 255],
+            Theme::Dark => [80, 250, 123, 255],
+            Theme::ColorBlind => [0, 114, 178, 255],
+        };
+        let annotation = match theme {
+            Theme::Classic => [0, 128, 0, 255],
+            Theme::Grayscale => [0, 0, 0, 255],
+            Theme::Dark => [241, 250, 140, 255],
+            Theme::ColorBlind => [230, 159, 0, 255],
+        };
+        Self {
+            theme,
+            free_color: free,
+            occupied_color: occupied,
+            unknown_color: unknown,
+            gamma: 1.,
+            path: LayerStyle::new(path),
+            reference_path: LayerStyle::new(reference_path),
+            scan: LayerStyle::new(scan),
+            scan_mode: ScanMode::default(),
+            color_by: ScanColoring::default(),
+            point_size: 2.,
+            show_max_range: false,
+            max_range: LayerStyle::new(max_range),
+            annotation: LayerStyle::new(annotation),
+            overlay: LayerStyle::new(overlay),
+            grid: LayerStyle::new(grid),
+            text_size: 13.,
+        }
+    }
+
+    /// Replace the colors by those of `theme`, the widths of the layers and
+    /// all the other settings are kept
+    pub fn set_theme(&mut self, theme: Theme) {
+        let colors = Self::from_theme(theme);
+        self.theme = theme;
+        self.free_color = colors.free_color;
+        self.occupied_color = colors.occupied_color;
+        self.unknown_color = colors.unknown_color;
+        for (layer, color) in [
+            (&mut self.path, colors.path),
+            (&mut self.reference_path, colors.reference_path),
+            (&mut self.scan, colors.scan),
+            (&mut self.max_range, colors.max_range),
+            (&mut self.annotation, colors.annotation),
+            (&mut self.overlay, colors.overlay),
+            (&mut self.grid, colors.grid),
+        ] {
+            layer.color = color.color;
+        }
+    }
+
+    /// Blend between the free and the occupied color, a negative occupancy
+    /// marks an unobserved cell
+    pub fn color_for_occ(&self, occ: f32) -> [u8; 4] {
+        if occ < 0. {
+            return self.unknown_color;
+        }
+        let occ = occ.powf(self.gamma);
+        let mut color = [0; 4];
+        for (c, (f, o)) in color
+            .iter_mut()
+            .zip(self.free_color.iter().zip(self.occupied_color.iter()))
+        {
+            *c = (*f as f32 + (*o as f32 - *f as f32) * occ) as u8;
+        }
+        color
+    }
+}
+
+/// Entries of a config which replace those of the theme
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct DrawingSettings {
+    theme: Theme,
+    free_color: Option<[u8; 4]>,
+    occupied_color: Option<[u8; 4]>,
+    unknown_color: Option<[u8; 4]>,
+    gamma: Option<f32>,
+    path: Option<LayerStyle>,
+    reference_path: Option<LayerStyle>,
+    scan: Option<LayerStyle>,
+    scan_mode: Option<ScanMode>,
+    color_by: Option<ScanColoring>,
+    point_size: Option<f32>,
+    show_max_range: Option<bool>,
+    max_range: Option<LayerStyle>,
+    annotation: Option<LayerStyle>,
+    overlay: Option<LayerStyle>,
+    grid: Option<LayerStyle>,
+    text_size: Option<f32>,
+}
+
+impl From<DrawingSettings> for DrawingParameter {
+    fn from(settings: DrawingSettings) -> Self {
+        let theme = Self::from_theme(settings.theme);
+        Self {
+            theme: settings.theme,
+            free_color: settings.free_color.unwrap_or(theme.free_color),
+            occupied_color: settings.occupied_color.unwrap_or(theme.occupied_color),
+            unknown_color: settings.unknown_color.unwrap_or(theme.unknown_color),
+            gamma: settings.gamma.unwrap_or(theme.gamma),
+            path: settings.path.unwrap_or(theme.path),
+            reference_path: settings.reference_path.unwrap_or(theme.reference_path),
+            scan: settings.scan.unwrap_or(theme.scan),
+            scan_mode: settings.scan_mode.unwrap_or(theme.scan_mode),
+            color_by: settings.color_by.unwrap_or(theme.color_by),
+            point_size: settings.point_size.unwrap_or(theme.point_size),
+            show_max_range: settings.show_max_range.unwrap_or(theme.show_max_range),
+            max_range: settings.max_range.unwrap_or(theme.max_range),
+            annotation: settings.annotation.unwrap_or(theme.annotation),
+            overlay: settings.overlay.unwrap_or(theme.overlay),
+            grid: settings.grid.unwrap_or(theme.grid),
+            text_size: settings.text_size.unwrap_or(theme.text_size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_replace_theme() {
+        let drawing: DrawingParameter = toml::from_str(
+            "theme = \"dark\"\nunknown_color = [0, 0, 0, 0]\n\n[scan]\ncolor = [1, 2, 3, 4]\nwidth = 2.5\n",
+        )
+        .unwrap();
+        let dark = DrawingParameter::from_theme(Theme::Dark);
+        assert_eq!(drawing.free_color, dark.free_color);
+        assert_eq!(drawing.path, dark.path);
+        assert_eq!(drawing.unknown_color, [0, 0, 0, 0]);
+        assert_eq!(drawing.scan.width, Some(2.5));
+
+        let text = toml::to_string(&drawing).unwrap();
+        assert_eq!(toml::from_str::<DrawingParameter>(&text).unwrap(), drawing);
+
+        let mut themed = DrawingParameter {
+            gamma: 0.5,
+            ..drawing
+        };
+        themed.set_theme(Theme::Grayscale);
+        let grayscale = DrawingParameter::from_theme(Theme::Grayscale);
+        assert_eq!(themed.unknown_color, grayscale.unknown_color);
+        assert_eq!(themed.scan.color, grayscale.scan.color);
+        assert_eq!(themed.scan.width, Some(2.5));
+        assert_eq!(themed.gamma, 0.5);
+    }
+}
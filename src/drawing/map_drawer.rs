@@ -1,104 +1,312 @@
 extern crate nalgebra as na;
 
-use std::path::PathBuf;
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::path::Path;
 
 use crate::{
-    datastream::robot_data::RobotLaser, rendering::map_creator_parameter::MapCreatorParameter,
+    datastream::robot_data::RobotLaser,
+    rendering::{
+        cellmap::OccupancyMap,
+        floatmap::FloatMap,
+        gridmap::{CellRegion, MapTransform},
+        map_creator::MapCreator,
+        map_creator_parameter::MapCreatorParameter,
+    },
 };
-use image::RgbaImage;
+use image::{ImageResult, RgbaImage};
+
+use super::animation::{self, AnimationParameter};
+use super::annotation::Annotations;
+use super::drawing_parameter::{colormap, DrawingParameter, LayerStyle, ScanColoring, ScanMode};
+use super::overlay::OverlayParameter;
+use super::text;
+use super::vector;
+
+/// Number of colors of the beams of a scan, each color is drawn as a layer of
+/// its own
+const SCAN_COLORS: usize = 32;
+
+/// Text drawn on top of the map
+#[derive(Debug, Clone)]
+pub struct Label {
+    ///< lower left corner of the text in world coordinates
+    pub position: [f64; 2],
+    pub text: String,
+}
+
+/// Overlay drawn on top of the map, kept in world coordinates for vector output
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub polylines: Vec<Vec<[f64; 2]>>,
+    pub points: Vec<[f64; 2]>,
+    pub labels: Vec<Label>,
+    pub color: [u8; 4],
+    ///< width of the stroke, diameter of the points or height of the labels in pixels
+    pub width: f32,
+}
+
+/// Pixels of the image are stored with premultiplied alpha
+fn premultiply([r, g, b, a]: [u8; 4]) -> [u8; 4] {
+    let c = tiny_skia::ColorU8::from_rgba(r, g, b, a).premultiply();
+    [c.red(), c.green(), c.blue(), c.alpha()]
+}
 
 pub struct MapDrawer {
     pub parameter: MapCreatorParameter,
+    pub drawing: DrawingParameter,
     pub offset: [f64; 2],
+    ///< time of the first and the last scan for coloring the scans by time
+    pub time_span: Option<(f64, f64)>,
+    ///< overlays drawn by `draw_overlays` and on every frame of an animation
+    pub overlays: OverlayParameter,
+    ///< annotations drawn by `draw_annotations` and on every frame of an animation
+    pub annotations: Annotations,
     pub img: tiny_skia::Pixmap,
-    backup: Option<Vec<u8>>,
+    base: Option<tiny_skia::Pixmap>,
+    layers: Vec<Layer>,
+    backup: Option<(Vec<u8>, usize)>,
 }
 
 impl MapDrawer {
     pub fn new(parameter: MapCreatorParameter, offset: [f64; 2], img: tiny_skia::Pixmap) -> Self {
         Self {
             parameter,
+            drawing: DrawingParameter::default(),
             offset,
+            time_span: None,
+            overlays: OverlayParameter::default(),
+            annotations: Annotations::default(),
             img,
+            base: None,
+            layers: Vec::new(),
             backup: None,
         }
     }
 
+    /// Create a drawer showing the occupancy of `fmap` in the given colors
+    pub fn from_map(
+        parameter: MapCreatorParameter,
+        drawing: DrawingParameter,
+        fmap: &FloatMap,
+    ) -> Self {
+        let size = tiny_skia::IntSize::from_wh(fmap.map.size[0] as u32, fmap.map.size[1] as u32);
+        let pixels = fmap.to_pixels(|occ| premultiply(drawing.color_for_occ(occ)));
+        let img = tiny_skia::Pixmap::from_vec(pixels, size.unwrap());
+        let mut drawer = Self::new(
+            parameter,
+            [fmap.map.offset.x, fmap.map.offset.y],
+            img.unwrap(),
+        );
+        drawer.drawing = drawing;
+        drawer
+    }
+
+    /// The map without any of the layers drawn on top
+    pub fn base_image(&self) -> &tiny_skia::Pixmap {
+        self.base.as_ref().unwrap_or(&self.img)
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
     pub fn has_backup(&self) -> bool {
         self.backup.is_some()
     }
 
     pub fn backup(&mut self) {
-        self.backup = Some(self.img.data().to_vec());
+        self.backup = Some((self.img.data().to_vec(), self.layers.len()));
     }
 
     pub fn restore_from_backup(&mut self) {
         match &self.backup {
-            Some(b) => self.img.data_mut().copy_from_slice(b),
+            Some((b, num_layers)) => {
+                self.img.data_mut().copy_from_slice(b);
+                self.layers.truncate(*num_layers);
+            }
             None => panic!("No backup"),
         }
         self.backup = None;
     }
 
-    pub fn to_image(&self) -> RgbaImage {
-        let w = self.img.width();
-        let h = self.img.height();
-        let mut rgba = RgbaImage::new(w, h);
-
-        for y in 0..h {
-            for x in 0..w {
-                let s = self.img.pixel(x, y).unwrap().demultiply();
-                let pixel = rgba.get_pixel_mut(x, y);
-                *pixel = image::Rgba([s.red(), s.green(), s.blue(), s.alpha()]);
-            }
+    /// RGBA data of the image, only translucent pixels need a conversion
+    pub fn rgba_data(&self) -> Vec<u8> {
+        let mut data = self.img.data().to_vec();
+        for p in data.chunks_exact_mut(4).filter(|p| p[3] != 255) {
+            let s = tiny_skia::PremultipliedColorU8::from_rgba(p[0], p[1], p[2], p[3])
+                .unwrap()
+                .demultiply();
+            p.copy_from_slice(&[s.red(), s.green(), s.blue(), s.alpha()]);
         }
-        rgba
+        data
     }
 
-    fn world2map(&self, wp: [f64; 2]) -> [f32; 2] {
-        let map_point = [
-            (wp[0] - self.offset[0]) / self.parameter.resolution,
-            self.img.height() as f64 - (wp[1] - self.offset[1]) / self.parameter.resolution,
-        ];
-        map_point.map(|c| c as f32)
+    pub fn to_image(&self) -> RgbaImage {
+        RgbaImage::from_raw(self.img.width(), self.img.height(), self.rgba_data()).unwrap()
     }
 
-    pub fn draw_path(&mut self, scans: &[RobotLaser]) {
-        if scans.len() < 2 {
+    /// Save the map, the extension of the filename selects the format.
+    ///
+    /// SVG and PDF files embed the map as image and contain the layers as
+    /// vector graphics, all other formats are written by the image crate.
+    pub fn save(&self, filename: &Path) -> ImageResult<()> {
+        match filename.extension().and_then(|e| e.to_str()) {
+            Some("svg") => vector::save_svg(self, filename),
+            Some("pdf") => vector::save_pdf(self, filename),
+            _ => self.to_image().save(filename),
+        }
+    }
+
+    /// Repaint the cells of a region, e.g., after integrating another scan.
+    ///
+    /// The cells of `map` have to match the pixels of the image, i.e., the
+    /// drawer was created from a dense map with the same size.
+    pub fn update_map_region(&mut self, map: &dyn OccupancyMap, region: &CellRegion) {
+        let (width, height) = (self.img.width() as usize, self.img.height() as usize);
+        if region.is_empty() || width == 0 || height == 0 {
             return;
         }
+        let x_max = region.max.x.min(width as i32 - 1);
+        let y_max = region.max.y.min(height as i32 - 1);
+        for y in region.min.y.max(0)..=y_max {
+            let row = height - 1 - y as usize;
+            for x in region.min.x.max(0)..=x_max {
+                let color = premultiply(self.drawing.color_for_occ(map.occupancy(x, y)));
+                let idx = 4 * (row * width + x as usize);
+                self.img.data_mut()[idx..idx + 4].copy_from_slice(&color);
+                if let Some(base) = self.base.as_mut() {
+                    base.data_mut()[idx..idx + 4].copy_from_slice(&color);
+                }
+            }
+        }
+    }
 
-        let mut paint = tiny_skia::Paint::default();
-        paint.set_color_rgba8(231, 130, 132, 255);
-        paint.anti_alias = true;
+    /// Pixel coordinates of a point given in world coordinates
+    pub(super) fn world2image(&self, wp: [f64; 2]) -> [f32; 2] {
+        let transform = MapTransform::new(self.parameter.resolution, self.offset.into());
+        let map_point = transform.world2map_exact(&wp.into());
+        [
+            map_point.x as f32,
+            (self.img.height() as f64 - map_point.y) as f32,
+        ]
+    }
+
+    /// World coordinates of a point given in pixels
+    pub(super) fn image2world(&self, p: [f32; 2]) -> [f64; 2] {
+        let transform = MapTransform::new(self.parameter.resolution, self.offset.into());
+        let map_point = na::Vector2::new(p[0] as f64, self.img.height() as f64 - p[1] as f64);
+        transform.map2world_exact(&map_point).into()
+    }
 
-        let path = {
+    pub(super) fn draw_layer(&mut self, layer: Layer) {
+        let lines = {
             let mut pb = tiny_skia::PathBuilder::new();
-            let pose = self.parameter.offset * scans[0].odom_pose;
-            let coords = self.world2map([pose.translation.x, pose.translation.y]);
-            pb.move_to(coords[0], coords[1]);
-            for s in scans.iter().skip(1) {
-                let pose = self.parameter.offset * s.odom_pose;
-                let coords = self.world2map([pose.translation.x, pose.translation.y]);
-                pb.line_to(coords[0], coords[1]);
+            for polyline in layer.polylines.iter().filter(|p| p.len() > 1) {
+                let coords = self.world2image(polyline[0]);
+                pb.move_to(coords[0], coords[1]);
+                for point in polyline.iter().skip(1) {
+                    let coords = self.world2image(*point);
+                    pb.line_to(coords[0], coords[1]);
+                }
             }
-            pb.finish().unwrap()
+            pb.finish()
+        };
+        let dots = {
+            let mut pb = tiny_skia::PathBuilder::new();
+            for point in layer.points.iter() {
+                let coords = self.world2image(*point);
+                pb.push_circle(coords[0], coords[1], layer.width / 2.);
+            }
+            pb.finish()
+        };
+        if lines.is_none() && dots.is_none() && layer.labels.is_empty() {
+            return;
+        }
+
+        let mut paint = tiny_skia::Paint {
+            anti_alias: true,
+            ..Default::default()
         };
+        let [r, g, b, a] = layer.color;
+        paint.set_color_rgba8(r, g, b, a);
 
         let stroke = tiny_skia::Stroke {
-            width: (self.parameter.path_width / self.parameter.resolution) as f32,
+            width: layer.width,
             ..Default::default()
         };
 
-        self.img.stroke_path(
-            &path,
-            &paint,
-            &stroke,
-            tiny_skia::Transform::identity(),
-            None,
-        );
+        if self.base.is_none() {
+            self.base = Some(self.img.clone());
+        }
+        if let Some(path) = lines {
+            self.img.stroke_path(
+                &path,
+                &paint,
+                &stroke,
+                tiny_skia::Transform::identity(),
+                None,
+            );
+        }
+        if let Some(path) = dots {
+            self.img.fill_path(
+                &path,
+                &paint,
+                tiny_skia::FillRule::Winding,
+                tiny_skia::Transform::identity(),
+                None,
+            );
+        }
+        for label in layer.labels.iter() {
+            let position = self.world2image(label.position);
+            text::draw_text(
+                &mut self.img,
+                position,
+                &label.text,
+                layer.width,
+                layer.color,
+            );
+        }
+        self.layers.push(layer);
+    }
+
+    /// Draw the path of the robot given by the odometry poses of the scans
+    pub fn draw_path<I>(&mut self, poses: I)
+    where
+        I: IntoIterator<Item = na::Isometry2<f64>>,
+    {
+        let style = self.drawing.path;
+        self.draw_path_with_style(poses, &style);
+    }
+
+    /// Draw a path in another style, e.g., the reference of an evaluation
+    pub fn draw_path_with_style<I>(&mut self, poses: I, style: &LayerStyle)
+    where
+        I: IntoIterator<Item = na::Isometry2<f64>>,
+    {
+        let polyline: Vec<[f64; 2]> = poses
+            .into_iter()
+            .map(|p| {
+                let pose = self.parameter.offset * p;
+                [pose.translation.x, pose.translation.y]
+            })
+            .collect();
+        if polyline.len() < 2 {
+            return;
+        }
+
+        self.draw_layer(Layer {
+            polylines: vec![polyline],
+            points: Vec::new(),
+            labels: Vec::new(),
+            color: style.color,
+            width: style
+                .width
+                .unwrap_or((self.parameter.path_width / self.parameter.resolution) as f32),
+        });
     }
 
+    /// Draw a scan in the scan mode and coloring of the drawing parameter
     pub fn draw_scan(&mut self, scan: &RobotLaser) {
         let usable_range = scan
             .laser_params
@@ -106,70 +314,197 @@ impl MapDrawer {
             .min(self.parameter.max_usable_range) as f32;
 
         let lpose = self.parameter.offset * scan.odom_pose * scan.laser_params.laser_pose;
-        let lcoords = self.world2map([lpose.translation.x, lpose.translation.y]);
-        let path = {
-            let mut pb = tiny_skia::PathBuilder::new();
-            for (i, r) in scan
-                .ranges
-                .iter()
-                .enumerate()
-                .filter(|&x| *x.1 < usable_range)
-            {
-                pb.move_to(lcoords[0], lcoords[1]);
-                let beam =
-                    lpose * scan.laser_params.beam_isometry(i) * na::Point2::new(*r as f64, 0.);
-                let coords = self.world2map([beam.x, beam.y]);
-                pb.line_to(coords[0], coords[1]);
+        let lcoords = [lpose.translation.x, lpose.translation.y];
+        let values = self.beam_values(scan, usable_range);
+        let scan_style = self.drawing.scan;
+
+        // one layer per color of the quantized color map, the readings at max
+        // range go into a layer of their own
+        let mut endpoints: BTreeMap<[u8; 4], Vec<[f64; 2]>> = BTreeMap::new();
+        let mut max_range_endpoints = Vec::new();
+        for (i, r) in scan.ranges.iter().enumerate() {
+            let max_range_reading = *r >= usable_range;
+            if max_range_reading && !self.drawing.show_max_range {
+                continue;
             }
-            pb.finish().unwrap()
-        };
+            let beam = lpose
+                * scan.laser_params.beam_isometry(i)
+                * na::Point2::new(r.min(usable_range) as f64, 0.);
+            if max_range_reading {
+                max_range_endpoints.push([beam.x, beam.y]);
+                continue;
+            }
+            let color = match &values {
+                Some(values) => {
+                    let steps = (SCAN_COLORS - 1) as f32;
+                    let [r, g, b] = colormap((values[i].clamp(0., 1.) * steps).round() / steps);
+                    [r, g, b, scan_style.color[3]]
+                }
+                None => scan_style.color,
+            };
+            endpoints.entry(color).or_default().push([beam.x, beam.y]);
+        }
 
-        let mut paint = tiny_skia::Paint {
-            anti_alias: true,
-            ..Default::default()
+        for (color, points) in endpoints {
+            let style = LayerStyle {
+                color,
+                ..scan_style
+            };
+            self.draw_endpoints(lcoords, points, &style);
+        }
+        if !max_range_endpoints.is_empty() {
+            let style = self.drawing.max_range;
+            self.draw_endpoints(lcoords, max_range_endpoints, &style);
+        }
+    }
+
+    /// Values between 0 and 1 selecting the colors of the beams, `None` if
+    /// all beams have the color of the scan style
+    fn beam_values(&self, scan: &RobotLaser, usable_range: f32) -> Option<Vec<f32>> {
+        let num_beams = scan.ranges.len();
+        match self.drawing.color_by {
+            ScanColoring::Uniform => None,
+            ScanColoring::BeamIndex => {
+                let last = num_beams.saturating_sub(1).max(1) as f32;
+                Some((0..num_beams).map(|i| i as f32 / last).collect())
+            }
+            ScanColoring::Range => Some(scan.ranges.iter().map(|r| r / usable_range).collect()),
+            ScanColoring::Remission => {
+                if scan.remissions.len() != num_beams {
+                    return None;
+                }
+                let max = scan.remissions.iter().copied().fold(0., f32::max);
+                let max = if max > 0. { max } else { 1. };
+                Some(scan.remissions.iter().map(|r| r / max).collect())
+            }
+            ScanColoring::Time => {
+                let (first, last) = self.time_span.filter(|(f, l)| l > f)?;
+                let value = (scan.timestamp() - first) / (last - first);
+                Some(vec![value as f32; num_beams])
+            }
+        }
+    }
+
+    /// Draw the beams from `origin` to the endpoints or only the endpoints
+    fn draw_endpoints(&mut self, origin: [f64; 2], endpoints: Vec<[f64; 2]>, style: &LayerStyle) {
+        let layer = match self.drawing.scan_mode {
+            ScanMode::Beams => Layer {
+                polylines: endpoints.into_iter().map(|e| vec![origin, e]).collect(),
+                points: Vec::new(),
+                labels: Vec::new(),
+                color: style.color,
+                width: style.width.unwrap_or(tiny_skia::Stroke::default().width),
+            },
+            ScanMode::Points => Layer {
+                polylines: Vec::new(),
+                points: endpoints,
+                labels: Vec::new(),
+                color: style.color,
+                width: self.drawing.point_size,
+            },
         };
-        paint.set_color_rgba8(242, 213, 207, 100);
+        self.draw_layer(layer);
+    }
 
-        let stroke = tiny_skia::Stroke::default();
+    /// Animate the scans over the map.
+    ///
+    /// The scans are consumed one at a time, `num_scans` is the number of
+    /// scans the iterator yields.
+    pub fn animate_scans<I>(
+        &mut self,
+        scans: I,
+        num_scans: usize,
+        output: &Path,
+        parameter: &AnimationParameter,
+    ) -> ImageResult<()>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<RobotLaser>,
+    {
+        self.animate(scans, num_scans, output, parameter, |_, _| {})
+    }
 
-        self.img.stroke_path(
-            &path,
-            &paint,
-            &stroke,
-            tiny_skia::Transform::identity(),
-            None,
-        );
+    /// Animate the scans while building the map, frame N shows the map of the
+    /// scans up to N. `map_creator` has to hold the allocated but empty map
+    /// this drawer was created from.
+    pub fn animate_map_building<I>(
+        &mut self,
+        scans: I,
+        num_scans: usize,
+        map_creator: &mut MapCreator,
+        output: &Path,
+        parameter: &AnimationParameter,
+    ) -> ImageResult<()>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<RobotLaser>,
+    {
+        self.animate(scans, num_scans, output, parameter, |drawer, rl| {
+            let region = map_creator.integrate_scan(rl);
+            drawer.update_map_region(map_creator.map.as_deref().unwrap(), &region);
+        })
     }
 
-    pub fn animate_scans(
+    /// Write one frame per selected scan, `update_map` is called for every
+    /// scan up to the last frame before drawing the frame of the scan
+    fn animate<I, F>(
         &mut self,
-        scans: &[RobotLaser],
-        output_folder: &PathBuf,
-        start: i32,
-        end: i32,
-        draw_path: bool,
-    ) {
-        let s = if start < 0 { 0 } else { start } as usize;
-        let e = if end < 0 { scans.len() } else { end as usize };
-        let width = (e - s).ilog10() as usize + 1;
-
-        for (i, scan) in scans[s..e].iter().enumerate() {
-            self.backup();
+        scans: I,
+        num_scans: usize,
+        output: &Path,
+        parameter: &AnimationParameter,
+        mut update_map: F,
+    ) -> ImageResult<()>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<RobotLaser>,
+        F: FnMut(&mut Self, &RobotLaser),
+    {
+        let s = parameter.start.min(num_scans);
+        let e = parameter.end.unwrap_or(num_scans).clamp(s, num_scans);
+        let step = parameter.step.max(1);
+        let num_frames = (s..e).step_by(step).len();
+        let print_progress = output != Path::new("-");
 
-            let image_name = PathBuf::from(format!("image_{:0width$}.png", i, width = width));
-            let filename: PathBuf = [output_folder, &image_name].iter().collect();
+        let mut sink = animation::create_sink(
+            output,
+            self.img.width(),
+            self.img.height(),
+            num_frames,
+            parameter.fps,
+        )?;
+        let mut path = Vec::new();
+        for (i, scan) in scans.into_iter().enumerate().take(e) {
+            let scan = scan.borrow();
+            update_map(self, scan);
+            if i < s {
+                continue;
+            }
+            path.push(scan.odom_pose);
+            if !(i - s).is_multiple_of(step) {
+                continue;
+            }
+            self.backup();
 
-            println!("Animate {} -> {}", s + i, filename.to_string_lossy());
-            if draw_path {
-                self.draw_path(&scans[s..=s + i]);
+            if print_progress {
+                match sink.frame_name() {
+                    Some(name) => println!("Animate {} -> {}", i, name),
+                    None => println!("Animate {}", i),
+                }
+            }
+            if parameter.draw_path {
+                self.draw_path(path.iter().copied());
             }
             self.draw_scan(scan);
+            self.draw_annotations();
+            self.draw_overlays(parameter.draw_path, true);
 
-            let _result = self.to_image().save(filename);
+            sink.add_frame(&self.rgba_data())?;
 
             assert!(self.has_backup());
             self.restore_from_backup();
         }
+        sink.finish()
     }
 }
 
@@ -189,4 +524,84 @@ mod tests {
         drawer.restore_from_backup();
         assert!(!drawer.has_backup());
     }
+
+    #[test]
+    fn restore_drops_layers() {
+        let params = MapCreatorParameter::default();
+        let pixmap = tiny_skia::Pixmap::new(10, 10).unwrap();
+        let mut drawer = MapDrawer::new(params, [0., 0.], pixmap);
+        drawer.backup();
+        drawer.draw_layer(Layer {
+            polylines: vec![vec![[0.1, 0.1], [0.5, 0.5]]],
+            points: Vec::new(),
+            labels: Vec::new(),
+            color: [255, 0, 0, 255],
+            width: 1.,
+        });
+        assert_eq!(drawer.layers().len(), 1);
+        assert_ne!(drawer.img.data(), drawer.base_image().data());
+        drawer.restore_from_backup();
+        assert!(drawer.layers().is_empty());
+        assert_eq!(drawer.img.data(), drawer.base_image().data());
+    }
+
+    #[test]
+    fn scan_points_by_index() {
+        use crate::datastream::robot_data::{test_scan, LaserParameters};
+
+        let scan = test_scan(na::Isometry2::identity(), vec![0.2, 0.3, 20., 0.3, 0.4]);
+        let pixmap = tiny_skia::Pixmap::new(10, 10).unwrap();
+        let mut drawer = MapDrawer::new(MapCreatorParameter::default(), [-0.5, -0.5], pixmap);
+        drawer.drawing.scan_mode = ScanMode::Points;
+        drawer.drawing.color_by = ScanColoring::BeamIndex;
+        drawer.draw_scan(&scan);
+        assert_eq!(drawer.layers().len(), 4);
+        assert!(drawer.layers().iter().all(|l| l.polylines.is_empty()));
+
+        drawer.layers.clear();
+        drawer.drawing.show_max_range = true;
+        drawer.draw_scan(&scan);
+        assert_eq!(drawer.layers().len(), 5);
+        assert_eq!(drawer.layers()[4].color, drawer.drawing.max_range.color);
+
+        let dense = RobotLaser {
+            laser_params: LaserParameters::new(na::Isometry2::identity(), -1.5, 0.01, 20.),
+            ..test_scan(na::Isometry2::identity(), vec![0.3; 301])
+        };
+        drawer.layers.clear();
+        drawer.draw_scan(&dense);
+        assert_eq!(drawer.layers().len(), SCAN_COLORS);
+    }
+
+    #[test]
+    fn update_region_matches_full_map() {
+        use crate::datastream::robot_data::test_scan;
+
+        let scans: Vec<RobotLaser> = [0., 1.]
+            .iter()
+            .map(|&x| {
+                let pose = na::Isometry2::new(na::Vector2::new(x, 0.), 0.3);
+                test_scan(pose, vec![2., 2.5, 3., 2.2, 4.])
+            })
+            .collect();
+        let mut map_creator = MapCreator::new(MapCreatorParameter::default());
+        map_creator.update_boundaries(&scans);
+        map_creator.allocate_map();
+
+        let parameter = map_creator.parameter;
+        let to_drawer = |map: &dyn OccupancyMap| {
+            MapDrawer::from_map(
+                parameter,
+                DrawingParameter::default(),
+                &map.compute_occupancy_map(),
+            )
+        };
+        let mut drawer = to_drawer(map_creator.map.as_deref().unwrap());
+        for rl in scans.iter() {
+            let region = map_creator.integrate_scan(rl);
+            drawer.update_map_region(map_creator.map.as_deref().unwrap(), &region);
+        }
+        let expected = to_drawer(map_creator.map.as_deref().unwrap());
+        assert_eq!(drawer.img.data(), expected.img.data());
+    }
 }
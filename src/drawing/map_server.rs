@@ -0,0 +1,104 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use image::codecs::pnm::{PnmEncoder, PnmSubtype, SampleEncoding};
+use image::{ImageEncoder, ImageResult};
+use serde::{Deserialize, Serialize};
+
+use crate::rendering::floatmap::FloatMap;
+
+const OCCUPIED: u8 = 0;
+const FREE: u8 = 254;
+const UNKNOWN: u8 = 205;
+
+/// Thresholds for converting the occupancy into the trinary map_server format
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MapServerParameter {
+    ///< cells with an occupancy above are considered occupied
+    pub occupied_thresh: f64,
+    ///< cells with an occupancy below are considered free
+    pub free_thresh: f64,
+}
+
+impl Default for MapServerParameter {
+    fn default() -> Self {
+        Self {
+            occupied_thresh: 0.65,
+            free_thresh: 0.196,
+        }
+    }
+}
+
+fn trinary_value(occ: f32, parameter: &MapServerParameter) -> u8 {
+    if occ < 0. {
+        UNKNOWN
+    } else if occ as f64 > parameter.occupied_thresh {
+        OCCUPIED
+    } else if (occ as f64) < parameter.free_thresh {
+        FREE
+    } else {
+        UNKNOWN
+    }
+}
+
+/// Save the map as trinary PGM image and the YAML description of map_server.
+///
+/// The YAML file is written next to the image with the extension `yaml`.
+pub fn save_map_server(
+    fmap: &FloatMap,
+    parameter: &MapServerParameter,
+    filename: &Path,
+) -> ImageResult<()> {
+    let width = fmap.map.size[0];
+    let height = fmap.map.size[1];
+    let mut pixels = Vec::with_capacity(width * height);
+    for i in 1..=height {
+        let y = height - i;
+        for x in 0..width {
+            let occ = fmap.map.cell(x as i32, y as i32).unwrap_or(&-1.);
+            pixels.push(trinary_value(*occ, parameter));
+        }
+    }
+
+    let writer = BufWriter::new(File::create(filename)?);
+    PnmEncoder::new(writer)
+        .with_subtype(PnmSubtype::Graymap(SampleEncoding::Binary))
+        .write_image(
+            &pixels,
+            width as u32,
+            height as u32,
+            image::ExtendedColorType::L8,
+        )?;
+
+    let image_name = filename.file_name().unwrap_or_default().to_string_lossy();
+    let mut yaml = BufWriter::new(File::create(filename.with_extension("yaml"))?);
+    writeln!(yaml, "image: {}", image_name)?;
+    writeln!(yaml, "resolution: {}", fmap.map.resolution)?;
+    writeln!(
+        yaml,
+        "origin: [{}, {}, 0.0]",
+        fmap.map.offset.x, fmap.map.offset.y
+    )?;
+    writeln!(yaml, "negate: 0")?;
+    writeln!(yaml, "occupied_thresh: {}", parameter.occupied_thresh)?;
+    writeln!(yaml, "free_thresh: {}", parameter.free_thresh)?;
+    yaml.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trinary() {
+        let parameter = MapServerParameter::default();
+        assert_eq!(trinary_value(-1., &parameter), UNKNOWN);
+        assert_eq!(trinary_value(0., &parameter), FREE);
+        assert_eq!(trinary_value(0.5, &parameter), UNKNOWN);
+        assert_eq!(trinary_value(0.9, &parameter), OCCUPIED);
+        assert_eq!(trinary_value(1.5, &parameter), OCCUPIED);
+    }
+}
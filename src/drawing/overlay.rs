@@ -0,0 +1,351 @@
+use serde::{Deserialize, Serialize};
+
+use super::drawing_parameter::{colormap, ScanColoring, ScanMode};
+use super::map_drawer::{Label, Layer, MapDrawer};
+use super::text;
+
+/// Colors of the x and the y axis from the Okabe-Ito palette
+const X_AXIS_COLOR: [u8; 4] = [213, 94, 0, 255];
+const Y_AXIS_COLOR: [u8; 4] = [0, 158, 115, 255];
+
+/// Distance of the overlays to the border of the image in pixels
+const MARGIN: f32 = 10.;
+
+/// Grids with more lines along one axis are not drawn
+const MAX_GRID_LINES: f64 = 500.;
+
+/// Optional overlays giving a metric reference
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OverlayParameter {
+    ///< draw a scale bar in the lower left corner
+    pub scale_bar: bool,
+    ///< length of the scale bar in meter, about a fifth of the map width if not given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale_bar_length: Option<f64>,
+    ///< spacing of the coordinate grid in meter, no grid if not given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grid: Option<f64>,
+    ///< mark the origin of the world and draw its x and y axis
+    pub axes: bool,
+    ///< explain the colors of the path and the scans in the upper left corner
+    pub legend: bool,
+}
+
+/// Largest length of the form 1, 2 or 5 times a power of ten not exceeding `length`
+pub fn nice_length(length: f64) -> f64 {
+    if length <= 0. || !length.is_finite() {
+        return 1.;
+    }
+    let magnitude = 10f64.powf(length.log10().floor());
+    let nice = [5., 2., 1.]
+        .into_iter()
+        .find(|f| f * magnitude <= length)
+        .unwrap_or(1.);
+    nice * magnitude
+}
+
+/// Meters without trailing zeros
+fn format_meters(value: f64) -> String {
+    format!("{}", (value * 1000.).round() / 1000. + 0.)
+}
+
+impl MapDrawer {
+    /// Draw the overlays selected by `self.overlays`, the legend lists the
+    /// path and the scans if they are shown
+    pub fn draw_overlays(&mut self, path: bool, scans: bool) {
+        let overlays = self.overlays;
+        if let Some(spacing) = overlays.grid {
+            self.draw_grid(spacing);
+        }
+        if overlays.axes {
+            self.draw_axes();
+        }
+        if overlays.scale_bar {
+            let width = self.img.width() as f64 * self.parameter.resolution;
+            let length = overlays
+                .scale_bar_length
+                .unwrap_or_else(|| nice_length(width / 5.));
+            self.draw_scale_bar(length);
+        }
+        if overlays.legend {
+            self.draw_legend(path, scans);
+        }
+    }
+
+    /// Layer of lines given in pixels
+    fn pixel_layer(&self, polylines: &[Vec<[f32; 2]>], color: [u8; 4], width: f32) -> Layer {
+        Layer {
+            polylines: polylines
+                .iter()
+                .map(|p| p.iter().map(|p| self.image2world(*p)).collect())
+                .collect(),
+            points: Vec::new(),
+            labels: Vec::new(),
+            color,
+            width,
+        }
+    }
+
+    /// Layer of texts given by their lower left corner in pixels
+    fn text_layer(&self, texts: Vec<([f32; 2], String)>, color: [u8; 4]) -> Layer {
+        Layer {
+            polylines: Vec::new(),
+            points: Vec::new(),
+            labels: texts
+                .into_iter()
+                .map(|(p, text)| Label {
+                    position: self.image2world(p),
+                    text,
+                })
+                .collect(),
+            color,
+            width: self.drawing.text_size,
+        }
+    }
+
+    /// Translucent box in the color of free space behind an overlay, given in
+    /// pixels. It is a single wide stroke to keep it a line in vector output.
+    fn draw_background(&mut self, left: f32, top: f32, right: f32, bottom: f32) {
+        let [r, g, b, _] = self.drawing.free_color;
+        let y = (top + bottom) / 2.;
+        let layer = self.pixel_layer(&[vec![[left, y], [right, y]]], [r, g, b, 200], bottom - top);
+        self.draw_layer(layer);
+    }
+
+    /// Lines at the multiples of `spacing` with their coordinates at the
+    /// lower and the left border of the image
+    pub fn draw_grid(&mut self, spacing: f64) {
+        let (width, height) = (self.img.width() as f32, self.img.height() as f32);
+        let min = self.image2world([0., height]);
+        let max = self.image2world([width, 0.]);
+        if spacing <= 0.
+            || (max[0] - min[0]) / spacing > MAX_GRID_LINES
+            || (max[1] - min[1]) / spacing > MAX_GRID_LINES
+        {
+            return;
+        }
+
+        let multiples = |min: f64, max: f64| {
+            ((min / spacing).ceil() as i64..=(max / spacing).floor() as i64)
+                .map(move |i| i as f64 * spacing)
+        };
+        let mut layer = Layer {
+            polylines: Vec::new(),
+            points: Vec::new(),
+            labels: Vec::new(),
+            color: self.drawing.grid.color,
+            width: self.drawing.grid.width.unwrap_or(1.),
+        };
+        let mut texts = Vec::new();
+        for x in multiples(min[0], max[0]) {
+            layer.polylines.push(vec![[x, min[1]], [x, max[1]]]);
+            let px = self.world2image([x, 0.])[0];
+            texts.push(([px + 2., height - 2.], format_meters(x)));
+        }
+        for y in multiples(min[1], max[1]) {
+            layer.polylines.push(vec![[min[0], y], [max[0], y]]);
+            let py = self.world2image([0., y])[1];
+            texts.push(([2., py - 2.], format_meters(y)));
+        }
+        self.draw_layer(layer);
+        let texts = self.text_layer(texts, self.drawing.overlay.color);
+        self.draw_layer(texts);
+    }
+
+    /// Bar of `length` meter with ticks at its ends in the lower left corner
+    pub fn draw_scale_bar(&mut self, length: f64) {
+        let style = self.drawing.overlay;
+        let bar = (length / self.parameter.resolution) as f32;
+        let tick = self.drawing.text_size / 2.;
+        let (x0, x1) = (MARGIN, MARGIN + bar);
+        let y = self.img.height() as f32 - MARGIN;
+        let label = format!("{} m", format_meters(length));
+        let [text_width, text_height] = text::text_size(&label, self.drawing.text_size);
+        let (text_width, text_height) = (text_width as f32, text_height as f32);
+        let pad = self.drawing.text_size / 2.;
+        self.draw_background(
+            x0.min((x0 + x1 - text_width) / 2.) - pad,
+            y - 4. - text_height - pad / 2.,
+            x1.max((x0 + x1 + text_width) / 2.) + pad,
+            y + pad,
+        );
+        let layer = self.pixel_layer(
+            &[vec![[x0, y - tick], [x0, y], [x1, y], [x1, y - tick]]],
+            style.color,
+            style.width.unwrap_or(2.),
+        );
+        self.draw_layer(layer);
+
+        let position = [(x0 + x1 - text_width) / 2., y - 4.];
+        let texts = self.text_layer(vec![(position, label)], style.color);
+        self.draw_layer(texts);
+    }
+
+    /// Marker at the origin of the world with arrows along the x and the y axis
+    pub fn draw_axes(&mut self) {
+        let style = self.drawing.overlay;
+        let width = style.width.unwrap_or(2.);
+        let origin = self.world2image([0., 0.]);
+        let length = 5. * self.drawing.text_size;
+        let head = 2. * width + 4.;
+
+        let [ox, oy] = origin;
+        let x_end = [ox + length, oy];
+        let y_end = [ox, oy - length];
+        let x_axis = self.pixel_layer(
+            &[
+                vec![origin, x_end],
+                vec![
+                    [x_end[0] - head, oy - head],
+                    x_end,
+                    [x_end[0] - head, oy + head],
+                ],
+            ],
+            X_AXIS_COLOR,
+            width,
+        );
+        let y_axis = self.pixel_layer(
+            &[
+                vec![origin, y_end],
+                vec![
+                    [ox - head, y_end[1] + head],
+                    y_end,
+                    [ox + head, y_end[1] + head],
+                ],
+            ],
+            Y_AXIS_COLOR,
+            width,
+        );
+        self.draw_layer(x_axis);
+        self.draw_layer(y_axis);
+
+        let text_size = self.drawing.text_size;
+        let x_label = self.text_layer(
+            vec![([x_end[0] + head, oy + text_size / 2.], "x".to_string())],
+            X_AXIS_COLOR,
+        );
+        let y_label = self.text_layer(
+            vec![([ox - text_size / 4., y_end[1] - head], "y".to_string())],
+            Y_AXIS_COLOR,
+        );
+        self.draw_layer(x_label);
+        self.draw_layer(y_label);
+        self.draw_layer(Layer {
+            polylines: Vec::new(),
+            points: vec![[0., 0.]],
+            labels: Vec::new(),
+            color: style.color,
+            width: 3. * width,
+        });
+    }
+
+    /// Swatches and names of the path and the scan colors in the upper left corner
+    pub fn draw_legend(&mut self, path: bool, scans: bool) {
+        let drawing = self.drawing;
+        let path_width = drawing
+            .path
+            .width
+            .unwrap_or((self.parameter.path_width / self.parameter.resolution) as f32);
+        let scan_width = match drawing.scan_mode {
+            ScanMode::Beams => drawing.scan.width.unwrap_or(1.),
+            ScanMode::Points => drawing.point_size,
+        };
+        let mut entries: Vec<(String, Vec<[u8; 4]>, f32)> = Vec::new();
+        if path {
+            entries.push(("path".to_string(), vec![drawing.path.color], path_width));
+        }
+        if scans {
+            let (name, colors) = match drawing.color_by {
+                ScanColoring::Uniform => ("scan".to_string(), vec![drawing.scan.color]),
+                color_by => {
+                    let name = match color_by {
+                        ScanColoring::BeamIndex => "beam index",
+                        ScanColoring::Range => "range",
+                        ScanColoring::Remission => "remission",
+                        _ => "time",
+                    };
+                    let colors = (0..8)
+                        .map(|i| {
+                            let [r, g, b] = colormap(i as f32 / 7.);
+                            [r, g, b, 255]
+                        })
+                        .collect();
+                    (format!("scan by {}", name), colors)
+                }
+            };
+            entries.push((name, colors, scan_width));
+            if drawing.show_max_range {
+                entries.push((
+                    "max range".to_string(),
+                    vec![drawing.max_range.color],
+                    drawing.max_range.width.unwrap_or(scan_width),
+                ));
+            }
+        }
+        if entries.is_empty() {
+            return;
+        }
+
+        let text_size = drawing.text_size;
+        let row = 1.5 * text_size;
+        let swatch = 2. * text_size;
+        let text_x = MARGIN + swatch + text_size / 2.;
+        let text_width = entries
+            .iter()
+            .map(|(name, _, _)| text::text_size(name, text_size)[0])
+            .max()
+            .unwrap_or(0) as f32;
+
+        let top = MARGIN - row / 4.;
+        self.draw_background(
+            MARGIN - text_size / 2.,
+            top,
+            text_x + text_width + text_size / 2.,
+            top + row * entries.len() as f32 + row / 2.,
+        );
+
+        let mut texts = Vec::new();
+        for (i, (name, colors, width)) in entries.into_iter().enumerate() {
+            let y = MARGIN + row * (i as f32 + 0.5);
+            let step = swatch / colors.len() as f32;
+            for (j, color) in colors.into_iter().enumerate() {
+                // thin strokes are widened to keep the color visible
+                let x = MARGIN + j as f32 * step;
+                let layer =
+                    self.pixel_layer(&[vec![[x, y], [x + step, y]]], color, width.clamp(3., row));
+                self.draw_layer(layer);
+            }
+            texts.push(([text_x, y + text_size / 2.], name));
+        }
+        let texts = self.text_layer(texts, drawing.overlay.color);
+        self.draw_layer(texts);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::map_creator_parameter::MapCreatorParameter;
+
+    #[test]
+    fn nice_lengths() {
+        assert_eq!(nice_length(7.3), 5.);
+        assert_eq!(nice_length(0.3), 0.2);
+        assert_eq!(nice_length(100.), 100.);
+        assert_eq!(format_meters(0.1 + 0.2), "0.3");
+        assert_eq!(format_meters(-0.), "0");
+    }
+
+    #[test]
+    fn grid_lines_at_multiples() {
+        let pixmap = tiny_skia::Pixmap::new(100, 50).unwrap();
+        let mut drawer = MapDrawer::new(MapCreatorParameter::default(), [-1.2, -0.3], pixmap);
+        drawer.draw_grid(2.);
+        let lines = &drawer.layers()[0].polylines;
+        let xs: Vec<f64> = lines.iter().map(|l| l[0][0]).take(5).collect();
+        assert_eq!(xs, [0., 2., 4., 6., 8.]);
+        assert_eq!(lines.len(), 5 + 3);
+        assert_eq!(drawer.layers()[1].labels[0].text, "0");
+    }
+}
@@ -0,0 +1,103 @@
+use std::convert::Infallible;
+
+use embedded_graphics::{
+    mono_font::{iso_8859_1, MonoFont, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    text::{Baseline, Text},
+};
+
+/// Bitmap fonts ordered by their height
+const FONTS: [&MonoFont<'static>; 4] = [
+    &iso_8859_1::FONT_6X10,
+    &iso_8859_1::FONT_7X13,
+    &iso_8859_1::FONT_9X15,
+    &iso_8859_1::FONT_10X20,
+];
+
+/// Largest font not exceeding the height in pixels, the largest font is
+/// scaled by an integer factor for larger texts
+fn font_for_size(size: f32) -> (&'static MonoFont<'static>, u32) {
+    let largest = FONTS[FONTS.len() - 1];
+    let largest_height = largest.character_size.height as f32;
+    if size >= 2. * largest_height {
+        return (largest, (size / largest_height) as u32);
+    }
+    let font = FONTS
+        .iter()
+        .rev()
+        .find(|f| f.character_size.height as f32 <= size)
+        .unwrap_or(&FONTS[0]);
+    (font, 1)
+}
+
+/// Width and height of a text in pixels
+pub fn text_size(text: &str, size: f32) -> [u32; 2] {
+    let (font, scale) = font_for_size(size);
+    let num_chars = text.chars().count() as u32;
+    let advance = font.character_size.width + font.character_spacing;
+    [
+        num_chars * advance * scale,
+        font.character_size.height * scale,
+    ]
+}
+
+/// Collects the pixels set by the font renderer
+struct GlyphPixels(Vec<Point>);
+
+impl OriginDimensions for GlyphPixels {
+    fn size(&self) -> Size {
+        Size::new(u32::MAX, u32::MAX)
+    }
+}
+
+impl DrawTarget for GlyphPixels {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        self.0
+            .extend(pixels.into_iter().filter(|p| p.1.is_on()).map(|p| p.0));
+        Ok(())
+    }
+}
+
+/// Draw a text with its lower left corner at `position` given in pixels
+pub fn draw_text(
+    img: &mut tiny_skia::Pixmap,
+    position: [f32; 2],
+    text: &str,
+    size: f32,
+    color: [u8; 4],
+) {
+    let (font, scale) = font_for_size(size);
+    let mut glyphs = GlyphPixels(Vec::new());
+    let style = MonoTextStyle::new(font, BinaryColor::On);
+    let _ = Text::with_baseline(text, Point::zero(), style, Baseline::Bottom).draw(&mut glyphs);
+
+    let (x0, y0) = (position[0].round(), position[1].round());
+    let mut pb = tiny_skia::PathBuilder::new();
+    for p in glyphs.0 {
+        let x = x0 + (p.x * scale as i32) as f32;
+        let y = y0 + (p.y * scale as i32) as f32;
+        if let Some(rect) = tiny_skia::Rect::from_xywh(x, y, scale as f32, scale as f32) {
+            pb.push_rect(rect);
+        }
+    }
+    let Some(path) = pb.finish() else {
+        return;
+    };
+    let mut paint = tiny_skia::Paint::default();
+    let [r, g, b, a] = color;
+    paint.set_color_rgba8(r, g, b, a);
+    img.fill_path(
+        &path,
+        &paint,
+        tiny_skia::FillRule::Winding,
+        tiny_skia::Transform::identity(),
+        None,
+    );
+}
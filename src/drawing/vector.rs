@@ -0,0 +1,329 @@
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use base64::Engine;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use image::{ImageError, ImageResult};
+
+use super::map_drawer::{Layer, MapDrawer};
+
+fn encoding_error(e: impl std::fmt::Display) -> ImageError {
+    ImageError::IoError(io::Error::other(e.to_string()))
+}
+
+/// Path data of the lines of a layer in world coordinates
+fn path_data(layer: &Layer) -> String {
+    let mut d = String::new();
+    for polyline in layer.polylines.iter().filter(|p| p.len() > 1) {
+        for (i, p) in polyline.iter().enumerate() {
+            let op = if i == 0 { 'M' } else { 'L' };
+            let _ = write!(d, "{}{:.4} {:.4} ", op, p[0], p[1]);
+        }
+    }
+    d
+}
+
+/// Path data of the points of a layer, drawn as zero length lines with round caps
+fn point_data(layer: &Layer) -> String {
+    let mut d = String::new();
+    for p in layer.points.iter() {
+        let _ = write!(d, "M{:.4} {:.4} h0 ", p[0], p[1]);
+    }
+    d
+}
+
+/// Escape the characters with a special meaning in XML
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Characters of the WinAnsi encoding (CP1252) at the codes 0x80 to 0x9f,
+/// which differ from Latin-1, unused codes are '\0'
+const WIN_ANSI_80_9F: [char; 32] = [
+    '€', '\0', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\0', 'Ž', '\0', // 0x80
+    '\0', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\0', 'ž', 'Ÿ', // 0x90
+];
+
+/// Code of a character in the WinAnsi encoding of the PDF font
+fn win_ansi(c: char) -> Option<u8> {
+    match c as u32 {
+        0..=0x7f | 0xa0..=0xff => Some(c as u8),
+        _ => WIN_ANSI_80_9F
+            .iter()
+            .position(|&w| w == c)
+            .map(|i| 0x80 + i as u8),
+    }
+}
+
+/// Encode text as PDF string for the WinAnsi encoding of the font, the
+/// characters with a special meaning and the codes outside of ASCII are
+/// escaped, characters without a code are replaced by '?'
+fn escape_pdf(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match win_ansi(c).unwrap_or(b'?') {
+            code @ (b'\\' | b'(' | b')') => {
+                escaped.push('\\');
+                escaped.push(code as char);
+            }
+            code @ 0x20..=0x7e => escaped.push(code as char),
+            code => {
+                let _ = write!(escaped, "\\{:03o}", code);
+            }
+        }
+    }
+    escaped
+}
+
+/// Save the map as SVG with the layers as paths in world coordinates
+pub fn save_svg(drawer: &MapDrawer, filename: &Path) -> ImageResult<()> {
+    let base = drawer.base_image();
+    let (w, h) = (base.width(), base.height());
+    let res = drawer.parameter.resolution;
+    let png = base.encode_png().map_err(encoding_error)?;
+
+    let mut svg = BufWriter::new(File::create(filename)?);
+    writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#
+    )?;
+    writeln!(
+        svg,
+        r#"<image width="{w}" height="{h}" style="image-rendering:pixelated" xlink:href="data:image/png;base64,{}"/>"#,
+        base64::engine::general_purpose::STANDARD.encode(png)
+    )?;
+    // flip the y axis and scale to pixels to draw in world coordinates
+    writeln!(
+        svg,
+        r#"<g fill="none" transform="matrix({} 0 0 {} {} {})">"#,
+        1. / res,
+        -1. / res,
+        -drawer.offset[0] / res,
+        h as f64 + drawer.offset[1] / res
+    )?;
+    for layer in drawer.layers() {
+        let [r, g, b, a] = layer.color;
+        for (d, cap) in [(path_data(layer), "butt"), (point_data(layer), "round")] {
+            if d.is_empty() {
+                continue;
+            }
+            writeln!(
+                svg,
+                r#"<path d="{}" stroke="rgb({r},{g},{b})" stroke-opacity="{:.3}" stroke-width="{}" stroke-linecap="{cap}"/>"#,
+                d.trim_end(),
+                a as f64 / 255.,
+                layer.width as f64 * res
+            )?;
+        }
+        // undo the flip of the y axis to keep the text upright
+        for label in layer.labels.iter() {
+            writeln!(
+                svg,
+                r#"<text transform="translate({:.4} {:.4}) scale(1 -1)" font-family="monospace" font-size="{}" fill="rgb({r},{g},{b})" fill-opacity="{:.3}">{}</text>"#,
+                label.position[0],
+                label.position[1],
+                layer.width as f64 * res,
+                a as f64 / 255.,
+                escape_xml(&label.text)
+            )?;
+        }
+    }
+    writeln!(svg, "</g>")?;
+    writeln!(svg, "</svg>")?;
+    svg.flush()?;
+    Ok(())
+}
+
+fn compress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+/// Minimal PDF writer keeping track of the object offsets for the xref table
+struct PdfWriter {
+    data: Vec<u8>,
+    offsets: Vec<usize>,
+}
+
+impl PdfWriter {
+    fn new() -> Self {
+        Self {
+            data: b"%PDF-1.4\n".to_vec(),
+            offsets: Vec::new(),
+        }
+    }
+
+    fn object(&mut self, id: usize, dict: &str) {
+        self.offsets.push(self.data.len());
+        self.data
+            .extend(format!("{} 0 obj\n{}\nendobj\n", id, dict).as_bytes());
+    }
+
+    fn stream(&mut self, id: usize, dict: &str, content: &[u8]) {
+        self.offsets.push(self.data.len());
+        self.data.extend(
+            format!(
+                "{} 0 obj\n<< {} /Filter /FlateDecode /Length {} >>\nstream\n",
+                id,
+                dict,
+                content.len()
+            )
+            .as_bytes(),
+        );
+        self.data.extend(content);
+        self.data.extend(b"\nendstream\nendobj\n");
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let xref = self.data.len();
+        let mut trailer = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for offset in self.offsets.iter() {
+            let _ = writeln!(trailer, "{:010} 00000 n ", offset);
+        }
+        let _ = write!(
+            trailer,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.offsets.len() + 1,
+            xref
+        );
+        self.data.extend(trailer.as_bytes());
+        self.data
+    }
+}
+
+/// Save the map as PDF with the layers as paths in world coordinates
+pub fn save_pdf(drawer: &MapDrawer, filename: &Path) -> ImageResult<()> {
+    let base = drawer.base_image();
+    let (w, h) = (base.width(), base.height());
+    let res = drawer.parameter.resolution;
+
+    let rgb: Vec<u8> = base
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let c = p.demultiply();
+            [c.red(), c.green(), c.blue()]
+        })
+        .collect();
+
+    let mut content = format!("q {w} 0 0 {h} 0 0 cm /Im0 Do Q\n");
+    let _ = writeln!(
+        content,
+        "q {} 0 0 {} {} {} cm",
+        1. / res,
+        1. / res,
+        -drawer.offset[0] / res,
+        -drawer.offset[1] / res
+    );
+    // one graphics state per layer for the transparency of the stroke and the text
+    let mut ext_states = String::new();
+    for (i, layer) in drawer.layers().iter().enumerate() {
+        let [r, g, b, _] = layer.color;
+        let _ = write!(ext_states, "/GS{} {} 0 R ", i, 6 + i);
+        let _ = writeln!(
+            content,
+            "/GS{} gs {:.4} {:.4} {:.4} RG {} w",
+            i,
+            r as f64 / 255.,
+            g as f64 / 255.,
+            b as f64 / 255.,
+            layer.width as f64 * res
+        );
+        let mut polylines = layer.polylines.iter().filter(|p| p.len() > 1).peekable();
+        if polylines.peek().is_some() {
+            for polyline in polylines {
+                for (j, p) in polyline.iter().enumerate() {
+                    let op = if j == 0 { 'm' } else { 'l' };
+                    let _ = writeln!(content, "{:.4} {:.4} {}", p[0], p[1], op);
+                }
+            }
+            content.push_str("S\n");
+        }
+        if !layer.points.is_empty() {
+            content.push_str("1 J\n");
+            for p in layer.points.iter() {
+                let _ = writeln!(
+                    content,
+                    "{:.4} {:.4} m {:.4} {:.4} l",
+                    p[0], p[1], p[0], p[1]
+                );
+            }
+            content.push_str("S 0 J\n");
+        }
+        if !layer.labels.is_empty() {
+            let _ = writeln!(
+                content,
+                "{:.4} {:.4} {:.4} rg",
+                r as f64 / 255.,
+                g as f64 / 255.,
+                b as f64 / 255.
+            );
+            for label in layer.labels.iter() {
+                let _ = writeln!(
+                    content,
+                    "BT /F1 {:.4} Tf {:.4} {:.4} Td ({}) Tj ET",
+                    layer.width as f64 * res,
+                    label.position[0],
+                    label.position[1],
+                    escape_pdf(&label.text)
+                );
+            }
+        }
+    }
+    content.push_str("Q\n");
+
+    let font = 6 + drawer.layers().len();
+    let mut pdf = PdfWriter::new();
+    pdf.object(1, "<< /Type /Catalog /Pages 2 0 R >>");
+    pdf.object(2, "<< /Type /Pages /Kids [3 0 R] /Count 1 >>");
+    pdf.object(
+        3,
+        &format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {w} {h}] /Contents 4 0 R \
+             /Resources << /XObject << /Im0 5 0 R >> /ExtGState << {ext_states}>> \
+             /Font << /F1 {font} 0 R >> >> >>"
+        ),
+    );
+    pdf.stream(4, "", &compress(content.as_bytes())?);
+    pdf.stream(
+        5,
+        &format!(
+            "/Type /XObject /Subtype /Image /Width {w} /Height {h} \
+             /ColorSpace /DeviceRGB /BitsPerComponent 8"
+        ),
+        &compress(&rgb)?,
+    );
+    for (i, layer) in drawer.layers().iter().enumerate() {
+        let alpha = layer.color[3] as f64 / 255.;
+        pdf.object(
+            6 + i,
+            &format!("<< /Type /ExtGState /CA {:.3} /ca {:.3} >>", alpha, alpha),
+        );
+    }
+    pdf.object(
+        font,
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Courier /Encoding /WinAnsiEncoding >>",
+    );
+
+    File::create(filename)?.write_all(&pdf.finish())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pdf_strings_in_win_ansi() {
+        assert_eq!(escape_pdf("a (b) \\"), "a \\(b\\) \\\\");
+        assert_eq!(escape_pdf("90° µm ä €"), "90\\260 \\265m \\344 \\200");
+        assert_eq!(escape_pdf("→ \u{81}"), "? ?");
+    }
+}
@@ -0,0 +1,302 @@
+extern crate nalgebra as na;
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::datastream::pose_file::{self, TimedPose};
+
+/// Rigid transformation minimizing the squared distances between the
+/// positions of `estimate` moved by it and those of `reference`, i.e., the
+/// Umeyama alignment without scale in the plane
+pub fn align_se2(
+    estimate: &[na::Vector2<f64>],
+    reference: &[na::Vector2<f64>],
+) -> na::Isometry2<f64> {
+    let n = estimate.len().min(reference.len());
+    if n == 0 {
+        return na::Isometry2::identity();
+    }
+    let centroid =
+        |points: &[na::Vector2<f64>]| points[..n].iter().sum::<na::Vector2<f64>>() / n as f64;
+    let (mu_e, mu_r) = (centroid(estimate), centroid(reference));
+    let (mut dot, mut cross) = (0., 0.);
+    for (e, r) in estimate.iter().zip(reference.iter()) {
+        let (a, b) = (e - mu_e, r - mu_r);
+        dot += a.dot(&b);
+        cross += a.perp(&b);
+    }
+    let rotation = na::UnitComplex::new(cross.atan2(dot));
+    na::Isometry2::from_parts((mu_r - rotation * mu_e).into(), rotation)
+}
+
+/// Summary of a set of errors
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ErrorStats {
+    pub count: usize,
+    pub rmse: f64,
+    pub mean: f64,
+    pub median: f64,
+    pub std: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl ErrorStats {
+    /// Statistics of the errors, `None` without any error
+    pub fn new(mut errors: Vec<f64>) -> Option<Self> {
+        if errors.is_empty() {
+            return None;
+        }
+        errors.sort_by(f64::total_cmp);
+        let n = errors.len();
+        let mean = errors.iter().sum::<f64>() / n as f64;
+        let squares = errors.iter().map(|e| e * e).sum::<f64>() / n as f64;
+        let median = if n % 2 == 1 {
+            errors[n / 2]
+        } else {
+            (errors[n / 2 - 1] + errors[n / 2]) / 2.
+        };
+        Some(Self {
+            count: n,
+            rmse: squares.sqrt(),
+            mean,
+            median,
+            std: (squares - mean * mean).max(0.).sqrt(),
+            min: errors[0],
+            max: errors[n - 1],
+        })
+    }
+}
+
+impl fmt::Display for ErrorStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "rmse {:.4}, mean {:.4}, median {:.4}, std {:.4}, min {:.4}, max {:.4}",
+            self.rmse, self.mean, self.median, self.std, self.min, self.max
+        )
+    }
+}
+
+/// Relative pose error over segments of a fixed length
+#[derive(Debug, Clone, Serialize)]
+pub struct SegmentErrors {
+    ///< length of the segments along the reference trajectory in meter
+    pub length: f64,
+    ///< translational error in meter, `None` if the trajectory is too short
+    pub translation: Option<ErrorStats>,
+    ///< rotational error in degrees
+    pub rotation: Option<ErrorStats>,
+}
+
+/// Errors of an estimated trajectory compared to a reference trajectory
+#[derive(Debug, Clone, Serialize)]
+pub struct Evaluation {
+    ///< number of estimated poses with a reference pose
+    pub num_poses: usize,
+    ///< transformation [x, y, theta in degrees] aligning the estimate with the reference
+    pub alignment: [f64; 3],
+    ///< absolute trajectory error in meter after the alignment
+    pub ate: ErrorStats,
+    ///< relative pose errors, one per segment length
+    pub rpe: Vec<SegmentErrors>,
+    ///< transformation moving the estimated poses onto the reference
+    #[serde(skip)]
+    pub transform: na::Isometry2<f64>,
+}
+
+/// Reasons why a trajectory cannot be evaluated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EvaluationError {
+    /// Less than two estimated poses have a reference pose
+    TooFewPoses,
+    /// A segment length of the relative pose error is not positive
+    SegmentLength(f64),
+}
+
+impl fmt::Display for EvaluationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvaluationError::TooFewPoses => write!(
+                f,
+                "less than two poses of the trajectory have a reference pose"
+            ),
+            EvaluationError::SegmentLength(length) => write!(
+                f,
+                "the segment lengths of the relative pose error have to be positive, got {}",
+                length
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EvaluationError {}
+
+/// Check that all the segment lengths are positive
+pub fn validate_segment_lengths(segment_lengths: &[f64]) -> Result<(), EvaluationError> {
+    match segment_lengths.iter().find(|l| l.is_nan() || **l <= 0.) {
+        Some(&length) => Err(EvaluationError::SegmentLength(length)),
+        None => Ok(()),
+    }
+}
+
+/// Associate the estimated poses with the reference poses interpolated at
+/// their timestamps, align the trajectories and compute the absolute and the
+/// relative errors. Fails if less than two poses can be associated or if a
+/// segment length is not positive.
+pub fn evaluate(
+    estimate: &[TimedPose],
+    reference: &[TimedPose],
+    segment_lengths: &[f64],
+) -> Result<Evaluation, EvaluationError> {
+    validate_segment_lengths(segment_lengths)?;
+    let (estimated, referenced): (Vec<_>, Vec<_>) = estimate
+        .iter()
+        .filter_map(|e| {
+            let r = pose_file::interpolate_pose(reference, e.timestamp)?;
+            Some((e.pose, r))
+        })
+        .unzip();
+    if estimated.len() < 2 {
+        return Err(EvaluationError::TooFewPoses);
+    }
+
+    let positions = |poses: &[na::Isometry2<f64>]| -> Vec<na::Vector2<f64>> {
+        poses.iter().map(|p| p.translation.vector).collect()
+    };
+    let transform = align_se2(&positions(&estimated), &positions(&referenced));
+    let ate = estimated
+        .iter()
+        .zip(referenced.iter())
+        .map(|(e, r)| ((transform * e).translation.vector - r.translation.vector).norm())
+        .collect();
+
+    // distance travelled along the reference up to each pose
+    let mut distances = vec![0.];
+    for pair in referenced.windows(2) {
+        let step = (pair[1].translation.vector - pair[0].translation.vector).norm();
+        distances.push(distances.last().unwrap() + step);
+    }
+    let rpe = segment_lengths
+        .iter()
+        .map(|&length| {
+            let (mut translation, mut rotation) = (Vec::new(), Vec::new());
+            for i in 0..referenced.len() {
+                let j = distances.partition_point(|d| d - distances[i] < length);
+                if j >= referenced.len() {
+                    break;
+                }
+                let relative_reference = referenced[i].inverse() * referenced[j];
+                let relative_estimate = estimated[i].inverse() * estimated[j];
+                let error = relative_reference.inverse() * relative_estimate;
+                translation.push(error.translation.vector.norm());
+                rotation.push(error.rotation.angle().abs().to_degrees());
+            }
+            SegmentErrors {
+                length,
+                translation: ErrorStats::new(translation),
+                rotation: ErrorStats::new(rotation),
+            }
+        })
+        .collect();
+
+    Ok(Evaluation {
+        num_poses: estimated.len(),
+        alignment: [
+            transform.translation.x,
+            transform.translation.y,
+            transform.rotation.angle().to_degrees(),
+        ],
+        ate: ErrorStats::new(ate).ok_or(EvaluationError::TooFewPoses)?,
+        rpe,
+        transform,
+    })
+}
+
+impl fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Associated poses: {}", self.num_poses)?;
+        let [x, y, theta] = self.alignment;
+        writeln!(f, "Alignment: {:.4} m, {:.4} m, {:.4} deg", x, y, theta)?;
+        writeln!(f, "ATE [m]: {}", self.ate)?;
+        for segment in self.rpe.iter() {
+            match (&segment.translation, &segment.rotation) {
+                (Some(translation), Some(rotation)) => {
+                    writeln!(
+                        f,
+                        "RPE over {} m ({} segments)",
+                        segment.length, translation.count
+                    )?;
+                    writeln!(f, "  translation [m]: {}", translation)?;
+                    writeln!(f, "  rotation [deg]: {}", rotation)?;
+                }
+                _ => writeln!(f, "RPE over {} m: trajectory too short", segment.length)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn align_and_evaluate() {
+        let reference: Vec<TimedPose> = (0..20)
+            .map(|i| TimedPose {
+                timestamp: i as f64,
+                pose: na::Isometry2::new(
+                    na::Vector2::new(i as f64 * 0.5, (i as f64 * 0.3).sin()),
+                    i as f64 * 0.1,
+                ),
+            })
+            .collect();
+        let offset = na::Isometry2::new(na::Vector2::new(3., -1.), 0.7);
+        let mut estimate: Vec<TimedPose> = reference
+            .iter()
+            .map(|p| TimedPose {
+                timestamp: p.timestamp,
+                pose: offset * p.pose,
+            })
+            .collect();
+        // without a reference pose at its time
+        estimate.push(TimedPose {
+            timestamp: 100.,
+            pose: na::Isometry2::identity(),
+        });
+
+        let evaluation = evaluate(&estimate, &reference, &[1., 100.]).unwrap();
+        assert_eq!(evaluation.num_poses, 20);
+        assert!((evaluation.alignment[2] + 0.7f64.to_degrees()).abs() < 1e-9);
+        assert!((evaluation.transform * offset).translation.vector.norm() < 1e-9);
+        assert!(evaluation.ate.rmse < 1e-9);
+        let rpe = &evaluation.rpe[0];
+        assert!(rpe.translation.unwrap().count > 10);
+        assert!(rpe.rotation.unwrap().max < 1e-9);
+        assert!(evaluation.rpe[1].translation.is_none());
+
+        let noisy: Vec<TimedPose> = reference
+            .iter()
+            .enumerate()
+            .map(|(i, p)| TimedPose {
+                timestamp: p.timestamp,
+                pose: p.pose * na::Isometry2::translation(if i % 2 == 0 { 0.1 } else { -0.1 }, 0.),
+            })
+            .collect();
+        let evaluation = evaluate(&noisy, &reference, &[]).unwrap();
+        assert!((evaluation.ate.rmse - 0.1).abs() < 0.02);
+
+        for length in [0., -1., f64::NAN] {
+            assert!(matches!(
+                evaluate(&noisy, &reference, &[1., length]),
+                Err(EvaluationError::SegmentLength(_))
+            ));
+        }
+        assert_eq!(
+            evaluate(&noisy[..1], &reference, &[]).unwrap_err(),
+            EvaluationError::TooFewPoses
+        );
+    }
+}
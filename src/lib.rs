@@ -0,0 +1,18 @@
+//! Render occupancy grid maps from laser range data.
+//!
+//! The [`datastream`] module reads CARMEN logs and g2o graphs, [`rendering`]
+//! integrates the scans into occupancy maps and [`drawing`] turns those into
+//! images, animations and map_server files. [`MapBuilder`] combines the steps
+//! for the common case and [`config`] stores all the settings in a file.
+//! [`stats`] summarizes a log and its map, [`evaluation`] compares its
+//! trajectory to a reference.
+
+pub mod config;
+pub mod datastream;
+pub mod drawing;
+pub mod evaluation;
+pub mod map_builder;
+pub mod rendering;
+pub mod stats;
+
+pub use map_builder::MapBuilder;
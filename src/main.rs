@@ -1,202 +1,1019 @@
 extern crate nalgebra as na;
 
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
 use std::io::Write;
-use std::iter::zip;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use clap::Parser as ClapParser;
 use clap::Subcommand as ClapSubCommand;
+use clap::{ArgAction, CommandFactory, FromArgMatches, ValueEnum};
+use serde::Serialize;
 
-mod datastream;
-use datastream::robot_data::RobotLaser;
-use datastream::{parser::Parser, parser_carmen::CarmenFile};
-mod rendering;
-use rendering::map_creator::MapCreator;
-use rendering::map_creator_parameter::MapCreatorParameter;
-mod drawing;
-use drawing::map_drawer::MapDrawer;
+use log2gfx::config::{self, Config};
+use log2gfx::datastream;
+use log2gfx::datastream::parser::{self, ParseError, ParseMode, Parsed};
+use log2gfx::datastream::parser_carmen::CarmenFile;
+use log2gfx::datastream::pose_file::{self, PoseAssociation, PoseFile, TimedPose};
+use log2gfx::datastream::robot_data::RobotLaser;
+use log2gfx::datastream::selection::KeyframeFilter;
+use log2gfx::drawing::annotation::Annotations;
+use log2gfx::drawing::drawing_parameter::{DrawingParameter, ScanColoring, ScanMode, Theme};
+use log2gfx::drawing::map_drawer::MapDrawer;
+use log2gfx::drawing::map_server;
+use log2gfx::drawing::overlay::OverlayParameter;
+use log2gfx::evaluation;
+use log2gfx::rendering::bresenham::RayTracer;
+use log2gfx::rendering::map_creator::MapCreator;
+use log2gfx::rendering::map_creator_parameter::MapModel;
+use log2gfx::stats::{MapStats, Statistics, TrajectoryStats};
+
+/// Stdout receives the output, diagnostics go to stderr instead
+static STDOUT_IS_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Print diagnostics to stdout, or to stderr if stdout receives the output
+fn diagnostic(message: fmt::Arguments) {
+    if STDOUT_IS_OUTPUT.load(Ordering::Relaxed) {
+        eprint!("{}", message);
+    } else {
+        print!("{}", message);
+        let _ = std::io::stdout().flush();
+    }
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        diagnostic(format_args!($($arg)*))
+    };
+}
+
+macro_rules! infoln {
+    ($($arg:tt)*) => {
+        diagnostic(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
 
 #[derive(ClapParser)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    after_help = "Flags given as --flag=false turn off a setting of the config file."
+)]
 struct Cli {
     /// Turn verbose logging on
     #[arg(short, long)]
     verbose: bool,
 
+    /// Read the settings from a TOML or YAML file, options given on the command line take precedence
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Save the effective settings as TOML file next to the output
+    #[arg(long)]
+    save_config: bool,
+
+    /// Color theme of the map, replaces the colors given in the config
+    #[arg(long, value_enum)]
+    theme: Option<Theme>,
+
     /// Resolution of the image in meter per pixel
-    #[arg(short, long, default_value_t = 0.1)]
-    resolution: f64,
+    #[arg(short, long)]
+    resolution: Option<f64>,
 
     /// Width for drawing the path of the robot
-    #[arg(long, default_value_t = 0.2)]
-    path_width: f64,
+    #[arg(long)]
+    path_width: Option<f64>,
 
     /// Offset for the map in [m, m, deg]
     #[arg(long, num_args = 3, allow_negative_numbers = true)]
     offset: Vec<f64>,
 
     /// Max range of the scans to integrate
-    #[arg(long, default_value_t = 20.)]
-    max_range: f64,
+    #[arg(long)]
+    max_range: Option<f64>,
 
     /// Max usable range of the scans to integrate
-    #[arg(long, default_value_t = 20.)]
-    max_usable_range: f64,
+    #[arg(long)]
+    max_usable_range: Option<f64>,
 
     /// Border around the map
-    #[arg(long, default_value_t = 2.)]
-    border: f64,
+    #[arg(long)]
+    border: Option<f64>,
 
     /// Zero the first pose of the trajectory
     #[arg(long)]
-    zero_first: bool,
+    zero_first: Option<bool>,
+
+    /// Model for integrating the scans into the map
+    #[arg(long, value_enum)]
+    map_model: Option<MapModel>,
+
+    /// Probability of a cell being occupied if a beam ends in it (log-odds model)
+    #[arg(long)]
+    prob_hit: Option<f64>,
+
+    /// Probability of a cell being occupied if a beam passes it (log-odds model)
+    #[arg(long)]
+    prob_miss: Option<f64>,
+
+    /// Lower bound for the occupancy probability of a cell (log-odds model)
+    #[arg(long)]
+    clamp_min: Option<f64>,
+
+    /// Upper bound for the occupancy probability of a cell (log-odds model)
+    #[arg(long)]
+    clamp_max: Option<f64>,
+
+    /// Number of threads for integrating the scans, 0 uses all cores, tiled maps are integrated on a single thread
+    #[arg(long)]
+    threads: Option<usize>,
+
+    /// Grow the map in tiles while integrating the scans in a single pass
+    #[arg(long)]
+    tiled: Option<bool>,
+
+    /// Algorithm for finding the cells along a beam
+    #[arg(long, value_enum)]
+    ray_tracer: Option<RayTracer>,
+
+    /// Stop at the first malformed line of the logfile instead of skipping it
+    #[arg(long)]
+    strict: Option<bool>,
+
+    /// Replace the poses of the scans by a trajectory given as `timestamp x y theta`
+    #[arg(long)]
+    poses: Option<PathBuf>,
+
+    /// How to associate the scans with the poses of the trajectory
+    #[arg(long, value_enum)]
+    associate_by: Option<PoseAssociation>,
+
+    /// Index of the first scan going into the map, counted over all scans of the log
+    #[arg(long)]
+    scan_start: Option<usize>,
+
+    /// Index after the last scan going into the map, counted over all scans of the log, all scans if not given
+    #[arg(long)]
+    scan_end: Option<usize>,
+
+    /// Only every n-th scan from the first one goes into the map
+    #[arg(long)]
+    scan_every: Option<usize>,
+
+    /// Drop the scans with an earlier timestamp
+    #[arg(long, allow_negative_numbers = true)]
+    start_time: Option<f64>,
+
+    /// Drop the scans with a later timestamp
+    #[arg(long, allow_negative_numbers = true)]
+    end_time: Option<f64>,
+
+    /// Keep a scan only after the robot moved this distance in meter since the last kept scan,
+    /// animations show the kept scans
+    #[arg(long)]
+    keyframe_distance: Option<f64>,
+
+    /// Keep a scan only after the robot turned this angle in degrees since the last kept scan
+    #[arg(long)]
+    keyframe_angle: Option<f64>,
+
+    /// Draw the markers, polylines and polygons of a TOML or YAML file on top of the map
+    #[arg(long)]
+    annotations: Option<PathBuf>,
 
     #[command(subcommand)]
     command: Command,
 
-    /// Input logfile for rendering
+    /// Input logfile for rendering, g2o/TORO graphs are detected by the extension and need ROBOTLASER lines after their vertices
     input: PathBuf,
 }
 
+/// Options for drawing the scans
+#[derive(clap::Args)]
+struct ScanStyleArgs {
+    /// Draw the scans as beams or as endpoints
+    #[arg(long, value_enum)]
+    scan_mode: Option<ScanMode>,
+    /// Attribute selecting the color of a beam
+    #[arg(long, value_enum)]
+    color_by: Option<ScanColoring>,
+    /// Diameter of the endpoints in pixels
+    #[arg(long)]
+    point_size: Option<f32>,
+    /// Draw the readings at the max usable range in a style of their own
+    #[arg(long)]
+    show_max_range: Option<bool>,
+}
+
+impl ScanStyleArgs {
+    fn apply(&self, drawing: &mut DrawingParameter) {
+        set(&self.scan_mode, &mut drawing.scan_mode);
+        set(&self.color_by, &mut drawing.color_by);
+        set(&self.point_size, &mut drawing.point_size);
+        set(&self.show_max_range, &mut drawing.show_max_range);
+    }
+}
+
+/// Overlays giving a metric reference
+#[derive(clap::Args)]
+struct OverlayArgs {
+    /// Draw a scale bar, its length in meter is chosen from the map width if not given
+    #[arg(long, value_name = "LENGTH", num_args = 0..=1)]
+    scale_bar: Option<Option<f64>>,
+    /// Draw grid lines in world coordinates with this spacing in meter
+    #[arg(long, value_name = "SPACING")]
+    grid: Option<f64>,
+    /// Mark the origin and draw the x and y axis of the world
+    #[arg(long)]
+    axes: Option<bool>,
+    /// Explain the colors of the path and the scans
+    #[arg(long)]
+    legend: Option<bool>,
+}
+
+impl OverlayArgs {
+    fn apply(&self, overlays: &mut OverlayParameter) {
+        if let Some(length) = self.scale_bar {
+            overlays.scale_bar = true;
+            if length.is_some() {
+                overlays.scale_bar_length = length;
+            }
+        }
+        if self.grid.is_some() {
+            overlays.grid = self.grid;
+        }
+        set(&self.axes, &mut overlays.axes);
+        set(&self.legend, &mut overlays.legend);
+    }
+}
+
 #[derive(ClapSubCommand)]
 enum Command {
     /// Render a single map image
     Render {
-        /// Highlight scans in the map
+        /// Highlight scans in the map, given by their index among the selected scans
         #[arg(long, num_args = 1..)]
         scan: Vec<usize>,
         /// Draw the path of the robot
         #[arg(long)]
-        draw_path: bool,
-        /// Output filename
-        #[arg(long, default_value = "log2gfx.png")]
-        output: PathBuf,
+        draw_path: Option<bool>,
+        #[command(flatten)]
+        scan_style: ScanStyleArgs,
+        #[command(flatten)]
+        overlay: OverlayArgs,
+        /// Output filename, the extension svg or pdf selects vector output
+        #[arg(long)]
+        output: Option<PathBuf>,
     },
     /// Perform animation of several images
     AnimateScans {
-        /// Start index of scans for animation
-        #[arg(long, default_value_t = -1)]
-        start: i32,
-        /// End index of scans for animation
-        #[arg(long, default_value_t = -1)]
-        end: i32,
+        /// Index of the first animated scan among the selected scans
+        #[arg(long)]
+        start: Option<usize>,
+        /// Index after the last animated scan among the selected scans, all scans if not given
+        #[arg(long)]
+        end: Option<usize>,
+        /// Only animate every n-th scan
+        #[arg(long)]
+        step: Option<usize>,
+        /// Frame rate of animated outputs
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        fps: Option<u32>,
         /// Draw the path of the robot
         #[arg(long)]
-        draw_path: bool,
-        /// Output path
-        #[arg(long, default_value = ".")]
-        output: PathBuf,
+        draw_path: Option<bool>,
+        /// Build the map while animating, frame N shows the map of scans 0..=N
+        #[arg(long)]
+        incremental: Option<bool>,
+        #[command(flatten)]
+        scan_style: ScanStyleArgs,
+        #[command(flatten)]
+        overlay: OverlayArgs,
+        /// Output folder for PNG images, a gif/png/y4m file or - for Y4M on stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Export the map as PGM image and YAML file for the ROS map_server
+    Export {
+        /// Cells with an occupancy above are considered occupied
+        #[arg(long)]
+        occupied_thresh: Option<f64>,
+        /// Cells with an occupancy below are considered free
+        #[arg(long)]
+        free_thresh: Option<f64>,
+        /// Output filename of the image, the YAML file is stored next to it
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Report statistics of the log and its map without rendering, cells are
+    /// classified by the thresholds of the export
+    Stats {
+        /// Width of the intervals of the range histogram in meter
+        #[arg(long)]
+        bin_width: Option<f64>,
+        /// Write the statistics as JSON
+        #[arg(long)]
+        json: Option<bool>,
+        /// Output filename or - for stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Compare the trajectory of the scans with a reference by the absolute
+    /// trajectory error and the relative pose error
+    Evaluate {
+        /// Reference trajectory given as `timestamp x y theta`, the TRUEPOS messages of the log if not given
+        #[arg(long)]
+        reference: Option<PathBuf>,
+        /// Lengths of the segments for the relative pose error in meter
+        #[arg(long, num_args = 1.., allow_negative_numbers = true)]
+        segment_length: Vec<f64>,
+        /// Write the results as JSON
+        #[arg(long)]
+        json: Option<bool>,
+        /// Render the map with the path and the aligned reference path into this file
+        #[arg(long)]
+        map: Option<PathBuf>,
+        /// Output filename or - for stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
     },
 }
 
-fn compute_length(scans: &[RobotLaser]) -> f64 {
-    let mut len = 0.;
-    for (curr, next) in zip(scans.iter(), scans.iter().skip(1)) {
-        len += (next.odom_pose.translation.vector - curr.odom_pose.translation.vector).norm();
-    }
-    len
+/// Append the default of an option to its help
+fn show_default(arg: clap::Arg, value: impl Display) -> clap::Arg {
+    let help = match arg.get_help() {
+        Some(help) => format!("{} [default: {}]", help, value),
+        None => format!("[default: {}]", value),
+    };
+    arg.help(help)
 }
 
-fn to_map_drawer(map_creator: MapCreator) -> MapDrawer {
-    let fmap = map_creator.fmap.as_ref().unwrap();
-    let width = fmap.map.size[0] as u32;
-    let height = fmap.map.size[1] as u32;
-    let img_data = fmap.compute_occupancy_map().to_pixels();
-    let img = tiny_skia::Pixmap::from_vec(
-        img_data,
-        tiny_skia::IntSize::from_wh(width, height).unwrap(),
-    );
-    MapDrawer::new(
-        map_creator.parameter,
-        [fmap.map.offset.x, fmap.map.offset.y],
-        img.unwrap(),
-    )
+/// Let the boolean options be given as `--flag` or as `--flag=BOOL`, the
+/// value has to be attached so that it is not confused with a positional argument
+fn bool_flags(command: clap::Command) -> clap::Command {
+    let bool_type = clap::builder::ValueParser::bool().type_id();
+    let flags: Vec<clap::Id> = command
+        .get_arguments()
+        .filter(|a| matches!(a.get_action(), ArgAction::Set))
+        .filter(|a| a.get_value_parser().type_id() == bool_type)
+        .map(|a| a.get_id().clone())
+        .collect();
+    let subcommands: Vec<String> = command
+        .get_subcommands()
+        .map(|c| c.get_name().to_string())
+        .collect();
+    let command = flags.iter().fold(command, |command, id| {
+        command.mut_arg(id, |a| {
+            a.value_name("BOOL")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("true")
+        })
+    });
+    subcommands.iter().fold(command, |command, name| {
+        command.mut_subcommand(name, bool_flags)
+    })
 }
 
-fn main() {
-    let cli = Cli::parse();
+/// Name of an enum value on the command line
+fn value_name<T: ValueEnum>(value: T) -> String {
+    value.to_possible_value().unwrap().get_name().to_string()
+}
+
+/// Command line interface with the defaults of the config shown in the help,
+/// the options only overwrite the config if they are given
+fn command() -> clap::Command {
+    let config = Config::default();
+    let (map, input, drawing) = (&config.map, &config.input, &config.drawing);
+    let selection = &input.selection;
+    let animation = &config.animate.animation;
+    let map_server = &config.export.map_server;
+    let scan_style = |command: clap::Command| {
+        command
+            .mut_arg("scan_mode", |a| {
+                show_default(a, value_name(drawing.scan_mode))
+            })
+            .mut_arg("color_by", |a| {
+                show_default(a, value_name(drawing.color_by))
+            })
+            .mut_arg("point_size", |a| show_default(a, drawing.point_size))
+    };
+    let output = |command: clap::Command, output: &Path| {
+        command.mut_arg("output", |a| show_default(a, output.display()))
+    };
+    let segment_lengths: Vec<String> = config
+        .evaluate
+        .segment_lengths
+        .iter()
+        .map(f64::to_string)
+        .collect();
+    bool_flags(Cli::command())
+        .mut_arg("theme", |a| show_default(a, value_name(Theme::default())))
+        .mut_arg("resolution", |a| show_default(a, map.resolution))
+        .mut_arg("path_width", |a| show_default(a, map.path_width))
+        .mut_arg("max_range", |a| show_default(a, map.max_range))
+        .mut_arg("max_usable_range", |a| {
+            show_default(a, map.max_usable_range)
+        })
+        .mut_arg("border", |a| show_default(a, map.border))
+        .mut_arg("map_model", |a| show_default(a, value_name(map.map_model)))
+        .mut_arg("prob_hit", |a| show_default(a, map.log_odds.prob_hit))
+        .mut_arg("prob_miss", |a| show_default(a, map.log_odds.prob_miss))
+        .mut_arg("clamp_min", |a| show_default(a, map.log_odds.clamp_min))
+        .mut_arg("clamp_max", |a| show_default(a, map.log_odds.clamp_max))
+        .mut_arg("threads", |a| show_default(a, map.threads))
+        .mut_arg("ray_tracer", |a| {
+            show_default(a, value_name(map.ray_tracer))
+        })
+        .mut_arg("associate_by", |a| {
+            show_default(a, value_name(input.associate_by))
+        })
+        .mut_arg("scan_start", |a| show_default(a, selection.start))
+        .mut_arg("scan_every", |a| show_default(a, selection.every))
+        .mut_subcommand("render", |c| output(scan_style(c), &config.render.output))
+        .mut_subcommand("animate-scans", |c| {
+            output(scan_style(c), &config.animate.output)
+                .mut_arg("start", |a| show_default(a, animation.start))
+                .mut_arg("step", |a| show_default(a, animation.step))
+                .mut_arg("fps", |a| show_default(a, animation.fps))
+        })
+        .mut_subcommand("export", |c| {
+            output(c, &config.export.output)
+                .mut_arg("occupied_thresh", |a| {
+                    show_default(a, map_server.occupied_thresh)
+                })
+                .mut_arg("free_thresh", |a| show_default(a, map_server.free_thresh))
+        })
+        .mut_subcommand("stats", |c| {
+            output(c, &config.stats.output)
+                .mut_arg("bin_width", |a| show_default(a, config.stats.bin_width))
+        })
+        .mut_subcommand("evaluate", |c| {
+            output(c, &config.evaluate.output).mut_arg("segment_length", |a| {
+                show_default(a, segment_lengths.join(" "))
+            })
+        })
+}
+
+/// Overwrite `target` if the option is given
+fn set<T: Clone>(value: &Option<T>, target: &mut T) {
+    if let Some(value) = value {
+        *target = value.clone();
+    }
+}
 
-    let offset = if !cli.offset.is_empty() {
-        na::Isometry2::new(
+/// Overwrite the settings of the config by the options given on the command line
+fn apply_cli(cli: &Cli, config: &mut Config) {
+    let map = &mut config.map;
+    set(&cli.resolution, &mut map.resolution);
+    set(&cli.path_width, &mut map.path_width);
+    if !cli.offset.is_empty() {
+        map.offset = na::Isometry2::new(
             na::Vector2::new(cli.offset[0], cli.offset[1]),
             cli.offset[2].to_radians(),
-        )
+        );
+    }
+    set(&cli.max_range, &mut map.max_range);
+    set(&cli.max_usable_range, &mut map.max_usable_range);
+    set(&cli.border, &mut map.border);
+    set(&cli.zero_first, &mut map.zero_first_pose);
+    set(&cli.map_model, &mut map.map_model);
+    set(&cli.prob_hit, &mut map.log_odds.prob_hit);
+    set(&cli.prob_miss, &mut map.log_odds.prob_miss);
+    set(&cli.clamp_min, &mut map.log_odds.clamp_min);
+    set(&cli.clamp_max, &mut map.log_odds.clamp_max);
+    set(&cli.threads, &mut map.threads);
+    set(&cli.tiled, &mut map.tiled);
+    set(&cli.ray_tracer, &mut map.ray_tracer);
+    map.verbose = cli.verbose;
+
+    if let Some(theme) = cli.theme {
+        config.drawing.set_theme(theme);
+    }
+
+    let input = &mut config.input;
+    if let Some(strict) = cli.strict {
+        input.parse_mode = if strict {
+            ParseMode::Strict
+        } else {
+            ParseMode::Lenient
+        };
+    }
+    if cli.poses.is_some() {
+        input.poses = cli.poses.clone();
+    }
+    set(&cli.associate_by, &mut input.associate_by);
+    let selection = &mut input.selection;
+    set(&cli.scan_start, &mut selection.start);
+    if cli.scan_end.is_some() {
+        selection.end = cli.scan_end;
+    }
+    set(&cli.scan_every, &mut selection.every);
+    if cli.start_time.is_some() {
+        selection.start_time = cli.start_time;
+    }
+    if cli.end_time.is_some() {
+        selection.end_time = cli.end_time;
+    }
+    if cli.keyframe_distance.is_some() {
+        selection.min_distance = cli.keyframe_distance;
+    }
+    if cli.keyframe_angle.is_some() {
+        selection.min_angle = cli.keyframe_angle;
+    }
+    if cli.annotations.is_some() {
+        input.annotations = cli.annotations.clone();
+    }
+
+    match &cli.command {
+        Command::Render {
+            scan,
+            draw_path,
+            scan_style,
+            overlay,
+            output,
+        } => {
+            scan_style.apply(&mut config.drawing);
+            overlay.apply(&mut config.overlays);
+            let render = &mut config.render;
+            if !scan.is_empty() {
+                render.scan = scan.clone();
+            }
+            set(draw_path, &mut render.draw_path);
+            set(output, &mut render.output);
+        }
+        Command::AnimateScans {
+            start,
+            end,
+            step,
+            fps,
+            draw_path,
+            incremental,
+            scan_style,
+            overlay,
+            output,
+        } => {
+            scan_style.apply(&mut config.drawing);
+            overlay.apply(&mut config.overlays);
+            let animate = &mut config.animate;
+            set(start, &mut animate.animation.start);
+            if end.is_some() {
+                animate.animation.end = *end;
+            }
+            set(step, &mut animate.animation.step);
+            set(fps, &mut animate.animation.fps);
+            set(draw_path, &mut animate.animation.draw_path);
+            set(incremental, &mut animate.incremental);
+            set(output, &mut animate.output);
+        }
+        Command::Export {
+            occupied_thresh,
+            free_thresh,
+            output,
+        } => {
+            let export = &mut config.export;
+            set(occupied_thresh, &mut export.map_server.occupied_thresh);
+            set(free_thresh, &mut export.map_server.free_thresh);
+            set(output, &mut export.output);
+        }
+        Command::Stats {
+            bin_width,
+            json,
+            output,
+        } => {
+            let stats = &mut config.stats;
+            set(bin_width, &mut stats.bin_width);
+            set(json, &mut stats.json);
+            set(output, &mut stats.output);
+        }
+        Command::Evaluate {
+            reference,
+            segment_length,
+            json,
+            map,
+            output,
+        } => {
+            let evaluate = &mut config.evaluate;
+            if reference.is_some() {
+                evaluate.reference = reference.clone();
+            }
+            if !segment_length.is_empty() {
+                evaluate.segment_lengths = segment_length.clone();
+            }
+            set(json, &mut evaluate.json);
+            if map.is_some() {
+                evaluate.map = map.clone();
+            }
+            set(output, &mut evaluate.output);
+        }
+    }
+}
+
+/// Filename of the output of a command, `-` for stdout
+fn output_filename<'a>(command: &Command, config: &'a Config) -> &'a Path {
+    match command {
+        Command::Render { .. } => &config.render.output,
+        Command::AnimateScans { .. } => &config.animate.output,
+        Command::Export { .. } => &config.export.output,
+        Command::Stats { .. } => &config.stats.output,
+        Command::Evaluate { .. } => &config.evaluate.output,
+    }
+}
+
+/// Write a report as text or JSON to a file or to stdout for `-`
+fn write_report<T: Serialize + Display>(report: &T, json: bool, output: &Path) {
+    let text = if json {
+        serde_json::to_string_pretty(report).unwrap() + "\n"
     } else {
-        na::Isometry2::identity()
+        report.to_string()
     };
-
-    let map_creator_parameter = MapCreatorParameter {
-        verbose: cli.verbose,
-        resolution: cli.resolution,
-        offset,
-        border: cli.border,
-        zero_first_pose: cli.zero_first,
-        path_width: cli.path_width,
-        max_range: cli.max_range,
-        max_usable_range: cli.max_usable_range,
+    let result = if output == Path::new("-") {
+        std::io::stdout().write_all(text.as_bytes())
+    } else {
+        std::fs::write(output, text)
     };
+    if let Err(e) = result {
+        eprintln!("Error: {}: {}", output.display(), e);
+        std::process::exit(1);
+    }
+}
+
+fn or_exit<T>(result: Result<T, ParseError>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn parsed_or_exit<T>(result: Result<Parsed<T>, ParseError>, verbose: bool) -> Vec<T> {
+    let parsed = or_exit(result);
+    if !parsed.skipped.is_empty() {
+        eprintln!("Skipped {} malformed lines", parsed.skipped.len());
+        if verbose {
+            for e in parsed.skipped.iter() {
+                eprintln!("  {}", e);
+            }
+        }
+    }
+    parsed.data
+}
 
-    let carmen_file = CarmenFile {
-        filename: cli.input,
+fn main() {
+    let cli = Cli::from_arg_matches(&command().get_matches()).unwrap_or_else(|e| e.exit());
+
+    let mut config = match &cli.config {
+        Some(filename) => Config::load(filename).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }),
+        None => Config::default(),
     };
-    let data = carmen_file.parse();
-    if cli.verbose {
-        println!("Number of laser readings: {}", data.len());
-        println!("Trajectory length: {:.3} m", compute_length(&data));
+    apply_cli(&cli, &mut config);
+    let stdout_is_output = output_filename(&cli.command, &config) == Path::new("-");
+    STDOUT_IS_OUTPUT.store(stdout_is_output, Ordering::Relaxed);
+    config.map.verbose_stderr = stdout_is_output;
+    let config = config;
+    if let Err(e) = config.map.log_odds.validate() {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    let mut stats = TrajectoryStats::new(config.stats.bin_width).unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    });
+
+    if let Err(e) = evaluation::validate_segment_lengths(&config.evaluate.segment_lengths) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    if let Err(e) = config.animate.animation.validate() {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
     }
 
-    let mut map_drawer = {
-        let mut map_creator = MapCreator::new(map_creator_parameter);
+    let config_output = cli.save_config.then(|| {
+        config::config_filename(output_filename(&cli.command, &config)).unwrap_or_else(|| {
+            eprintln!("Error: cannot save the config next to an output on stdout");
+            std::process::exit(1);
+        })
+    });
+
+    let parser = datastream::parser_for(cli.input.clone());
+    let mode = config.input.parse_mode;
+    let poses = config.input.poses.clone().map(|filename| {
+        let pose_file = PoseFile { filename };
+        parsed_or_exit(pose_file.parse(mode), cli.verbose)
+    });
+    let reference = matches!(cli.command, Command::Evaluate { .. }).then(|| {
+        let reference = match &config.evaluate.reference {
+            Some(filename) => {
+                let pose_file = PoseFile {
+                    filename: filename.clone(),
+                };
+                parsed_or_exit(pose_file.parse(mode), cli.verbose)
+            }
+            None => {
+                let log = CarmenFile {
+                    filename: cli.input.clone(),
+                };
+                parsed_or_exit(log.true_poses(mode), cli.verbose)
+            }
+        };
+        if reference.is_empty() {
+            eprintln!("Error: the reference trajectory is empty");
+            std::process::exit(1);
+        }
+        reference
+    });
+    let annotations = match &config.input.annotations {
+        Some(filename) => Annotations::load(filename).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }),
+        None => Annotations::default(),
+    };
+
+    // each call streams the selected scans from the beginning of the logfile,
+    // the poses of a trajectory are associated by the index within the log
+    let num_selected = Cell::new(0);
+    let num_posed = Cell::new(0);
+    let num_skipped = Cell::new(0);
+    let read_scans = |report_skipped: bool| {
+        let (num_selected, num_posed, num_skipped, poses) =
+            (&num_selected, &num_posed, &num_skipped, &poses);
+        let (verbose, associate_by) = (cli.verbose, config.input.associate_by);
+        let selection = config.input.selection;
+        let mut keyframes = KeyframeFilter::new(&selection);
+        let scans = or_exit(parser.scans());
+        let scans = parser::skip_malformed(scans, mode, move |e| {
+            if report_skipped {
+                num_skipped.set(num_skipped.get() + 1);
+                if verbose {
+                    eprintln!("  {}", e);
+                }
+            }
+        });
+        selection
+            .apply_enumerated(scans.map(or_exit))
+            .inspect(move |_| num_selected.set(num_selected.get() + 1))
+            .filter_map(move |(i, rl)| match poses {
+                Some(poses) => pose_file::replace_pose(rl, i, poses, associate_by),
+                None => Some(rl),
+            })
+            .inspect(move |_| num_posed.set(num_posed.get() + 1))
+            .filter(move |rl| keyframes.accept(&rl.odom_pose))
+    };
 
-        map_creator.update_boundaries(&data);
+    // the incremental animation integrates the scans while drawing the frames
+    let tiled = config.map.tiled;
+    let incremental =
+        matches!(cli.command, Command::AnimateScans { .. }) && config.animate.incremental;
+    if incremental && tiled {
+        eprintln!("Error: the incremental animation requires a map without tiles");
+        std::process::exit(1);
+    }
+    let require_scans = |stats: &TrajectoryStats| {
+        if stats.num_scans == 0 {
+            eprintln!("Error: no scans selected");
+            std::process::exit(1);
+        }
+    };
+    let report = |stats: &TrajectoryStats| {
+        if num_skipped.get() > 0 {
+            eprintln!("Skipped {} malformed lines", num_skipped.get());
+        }
+        if cli.verbose {
+            if poses.is_some() {
+                infoln!(
+                    "Replaced poses of {} scans, dropped {} without a pose",
+                    num_posed.get(),
+                    num_selected.get() - num_posed.get()
+                );
+            }
+            if num_posed.get() > stats.num_scans {
+                infoln!(
+                    "Kept {} of {} scans as keyframes",
+                    stats.num_scans,
+                    num_posed.get()
+                );
+            }
+            info!("{}", stats);
+        }
+    };
+
+    // first pass over the log to determine the size of the map, a tiled map
+    // grows while integrating the scans instead
+    let mut map_creator = MapCreator::new(config.map);
+    let mut trajectory = Vec::new();
+    let timed_pose = |rl: &RobotLaser| TimedPose {
+        timestamp: rl.timestamp(),
+        pose: rl.odom_pose,
+    };
+    // the evaluation only needs the poses unless it draws them on the map
+    let build_map =
+        !matches!(cli.command, Command::Evaluate { .. }) || config.evaluate.map.is_some();
+    if !build_map {
+        trajectory = read_scans(true)
+            .inspect(|rl| stats.add(rl))
+            .map(|rl| timed_pose(&rl))
+            .collect();
+        report(&stats);
+        require_scans(&stats);
+    } else if !tiled {
+        map_creator.update_boundaries(read_scans(true).inspect(|rl| stats.add(rl)));
+        report(&stats);
+        require_scans(&stats);
+    }
+    if build_map {
         map_creator.allocate_map();
-        map_creator.integrate_scans(&data);
-        to_map_drawer(map_creator)
+    }
+
+    // second pass integrating the scans, keeping only what is drawn later
+    let mut path = Vec::new();
+    let mut selected_scans = BTreeMap::new();
+    if build_map && !incremental {
+        let render = matches!(cli.command, Command::Render { .. }).then_some(&config.render);
+        let scans = read_scans(tiled).enumerate().inspect(|(i, rl)| {
+            if tiled {
+                stats.add(rl);
+            }
+            if reference.is_some() {
+                trajectory.push(timed_pose(rl));
+            }
+            if let Some(render) = render {
+                if render.draw_path {
+                    path.push(rl.odom_pose);
+                }
+                if render.scan.contains(i) {
+                    selected_scans.insert(*i, rl.clone());
+                }
+            }
+        });
+        map_creator.integrate_scans(scans.map(|(_, rl)| rl));
+        if tiled {
+            report(&stats);
+            require_scans(&stats);
+        }
+    }
+    let parameter = map_creator.parameter;
+    let occupancy_map = |map_creator: &MapCreator| {
+        let map = map_creator.map.as_ref().unwrap();
+        map.compute_occupancy_map()
     };
 
     match &cli.command {
-        Command::Render {
-            scan,
-            draw_path,
-            output,
-        } => {
-            if *draw_path {
+        Command::Render { .. } => {
+            let render = &config.render;
+            let fmap = occupancy_map(&map_creator);
+            let mut map_drawer = MapDrawer::from_map(parameter, config.drawing, &fmap);
+            map_drawer.time_span = stats.time_span;
+            map_drawer.overlays = config.overlays;
+            map_drawer.annotations = annotations;
+            if render.draw_path {
                 if cli.verbose {
-                    print!("Drawing the path ... ");
-                    let _ = std::io::stdout().flush();
+                    info!("Drawing the path ... ");
                 }
-                map_drawer.draw_path(&data);
+                map_drawer.draw_path(path);
                 if cli.verbose {
-                    println!("done.")
+                    infoln!("done.")
                 }
             }
 
-            if !scan.is_empty() {
+            if !render.scan.is_empty() {
                 if cli.verbose {
-                    print!("Drawing scans ... ");
-                    let _ = std::io::stdout().flush();
+                    info!("Drawing scans ... ");
                 }
-                for idx in scan.iter().filter(|&x| *x < data.len()) {
+                for idx in render.scan.iter() {
+                    let Some(rl) = selected_scans.get(idx) else {
+                        continue;
+                    };
                     if cli.verbose {
-                        print!("{} ", idx);
-                        let _ = std::io::stdout().flush();
+                        info!("{} ", idx);
                     }
-                    map_drawer.draw_scan(&data[*idx]);
+                    map_drawer.draw_scan(rl);
                 }
                 if cli.verbose {
-                    println!("done.")
+                    infoln!("done.")
                 }
             }
+            map_drawer.draw_annotations();
+            map_drawer.draw_overlays(render.draw_path, !render.scan.is_empty());
             if cli.verbose {
-                println!("Saving {}", output.to_string_lossy());
+                infoln!("Saving {}", render.output.to_string_lossy());
+            }
+            if let Err(e) = map_drawer.save(&render.output) {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
             }
-            let _result = map_drawer.to_image().save(output);
         }
-        Command::AnimateScans {
-            start,
-            end,
-            draw_path,
-            output,
-        } => map_drawer.animate_scans(&data, output, *start, *end, *draw_path),
+        Command::AnimateScans { .. } => {
+            let animate = &config.animate;
+            let fmap = occupancy_map(&map_creator);
+            let mut map_drawer = MapDrawer::from_map(parameter, config.drawing, &fmap);
+            map_drawer.time_span = stats.time_span;
+            map_drawer.overlays = config.overlays;
+            map_drawer.annotations = annotations;
+            let result = if incremental {
+                map_drawer.animate_map_building(
+                    read_scans(false),
+                    stats.num_scans,
+                    &mut map_creator,
+                    &animate.output,
+                    &animate.animation,
+                )
+            } else {
+                map_drawer.animate_scans(
+                    read_scans(false),
+                    stats.num_scans,
+                    &animate.output,
+                    &animate.animation,
+                )
+            };
+            if let Err(e) = result {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Export { .. } => {
+            let export = &config.export;
+            let fmap = occupancy_map(&map_creator);
+            if cli.verbose {
+                infoln!("Saving {}", export.output.to_string_lossy());
+            }
+            if let Err(e) = map_server::save_map_server(&fmap, &export.map_server, &export.output) {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Stats { .. } => {
+            let statistics = Statistics {
+                trajectory: stats,
+                map: MapStats::new(&occupancy_map(&map_creator), &config.export.map_server),
+            };
+            write_report(&statistics, config.stats.json, &config.stats.output);
+        }
+        Command::Evaluate { .. } => {
+            let evaluate = &config.evaluate;
+            let reference = reference.unwrap();
+            let evaluation =
+                evaluation::evaluate(&trajectory, &reference, &evaluate.segment_lengths)
+                    .unwrap_or_else(|e| {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(1);
+                    });
+            write_report(&evaluation, evaluate.json, &evaluate.output);
+
+            if let Some(filename) = &evaluate.map {
+                let fmap = occupancy_map(&map_creator);
+                let mut map_drawer = MapDrawer::from_map(parameter, config.drawing, &fmap);
+                map_drawer.draw_path(trajectory.iter().map(|p| p.pose));
+                let to_estimate = evaluation.transform.inverse();
+                let style = map_drawer.drawing.reference_path;
+                map_drawer
+                    .draw_path_with_style(reference.iter().map(|p| to_estimate * p.pose), &style);
+                if cli.verbose {
+                    infoln!("Saving {}", filename.to_string_lossy());
+                }
+                if let Err(e) = map_drawer.save(filename) {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+
+    if let Some(filename) = config_output {
+        if cli.verbose {
+            infoln!("Saving {}", filename.to_string_lossy());
+        }
+        if let Err(e) = config.save(&filename) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_override_the_config() {
+        command().debug_assert();
+        let mut config = Config::default();
+        config.map.tiled = true;
+        config.render.draw_path = true;
+        let args = ["log2gfx", "--tiled=false", "log.gfs", "render", "--legend"];
+        let cli = Cli::from_arg_matches(&command().get_matches_from(args)).unwrap();
+        apply_cli(&cli, &mut config);
+        assert!(!config.map.tiled);
+        assert!(config.render.draw_path);
+        assert!(config.overlays.legend);
     }
 }
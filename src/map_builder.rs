@@ -0,0 +1,295 @@
+extern crate nalgebra as na;
+
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::datastream::{
+    self,
+    parser::{self, ParseError, ParseMode, Parser},
+    robot_data::RobotLaser,
+    selection::ScanSelection,
+};
+use crate::drawing::{
+    drawing_parameter::{DrawingParameter, Theme},
+    map_drawer::MapDrawer,
+};
+use crate::rendering::{
+    bresenham::RayTracer,
+    logoddsmap::{LogOddsError, LogOddsParameter},
+    map_creator::MapCreator,
+    map_creator_parameter::{MapCreatorParameter, MapModel},
+};
+
+/// Error of building a map with a `MapBuilder`
+#[derive(Debug)]
+pub enum BuildError {
+    /// The logfile could not be parsed
+    Parse(ParseError),
+    /// None of the scans is selected, i.e., there is nothing to build a map from
+    NoScans,
+    /// The parameters of the log-odds model are invalid
+    LogOdds(LogOddsError),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Parse(e) => write!(f, "{}", e),
+            BuildError::NoScans => write!(f, "no scans selected"),
+            BuildError::LogOdds(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BuildError::Parse(e) => Some(e),
+            BuildError::NoScans => None,
+            BuildError::LogOdds(e) => Some(e),
+        }
+    }
+}
+
+impl From<ParseError> for BuildError {
+    fn from(e: ParseError) -> Self {
+        BuildError::Parse(e)
+    }
+}
+
+impl From<LogOddsError> for BuildError {
+    fn from(e: LogOddsError) -> Self {
+        BuildError::LogOdds(e)
+    }
+}
+
+/// Entry point going from a logfile to a rendered map.
+///
+/// ```no_run
+/// use log2gfx::MapBuilder;
+///
+/// let builder = MapBuilder::new().resolution(0.05);
+/// let scans = builder.read("dataset.log")?;
+/// let mut drawer = builder.render(&scans)?;
+/// drawer.draw_path(scans.iter().map(|s| s.odom_pose));
+/// drawer.save("map.png".as_ref())?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MapBuilder {
+    pub parameter: MapCreatorParameter,
+    pub drawing: DrawingParameter,
+    pub parse_mode: ParseMode,
+    pub selection: ScanSelection,
+}
+
+impl MapBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolution of the map in meter per pixel
+    pub fn resolution(mut self, resolution: f64) -> Self {
+        self.parameter.resolution = resolution;
+        self
+    }
+
+    /// Transformation applied to all poses
+    pub fn offset(mut self, offset: na::Isometry2<f64>) -> Self {
+        self.parameter.offset = offset;
+        self
+    }
+
+    /// Border around the map in meter
+    pub fn border(mut self, border: f64) -> Self {
+        self.parameter.border = border;
+        self
+    }
+
+    /// Width for drawing the path of the robot in meter
+    pub fn path_width(mut self, path_width: f64) -> Self {
+        self.parameter.path_width = path_width;
+        self
+    }
+
+    /// Beams of this length or longer are not integrated
+    pub fn max_range(mut self, max_range: f64) -> Self {
+        self.parameter.max_range = max_range;
+        self
+    }
+
+    /// Beams are cropped to this length
+    pub fn max_usable_range(mut self, max_usable_range: f64) -> Self {
+        self.parameter.max_usable_range = max_usable_range;
+        self
+    }
+
+    /// Move the first pose of the trajectory into the origin
+    pub fn zero_first_pose(mut self, zero_first_pose: bool) -> Self {
+        self.parameter.zero_first_pose = zero_first_pose;
+        self
+    }
+
+    pub fn map_model(mut self, map_model: MapModel) -> Self {
+        self.parameter.map_model = map_model;
+        self
+    }
+
+    pub fn log_odds(mut self, log_odds: LogOddsParameter) -> Self {
+        self.parameter.log_odds = log_odds;
+        self
+    }
+
+    /// Colors of the rendered map
+    pub fn drawing(mut self, drawing: DrawingParameter) -> Self {
+        self.drawing = drawing;
+        self
+    }
+
+    /// Colors of the rendered map taken from a theme
+    pub fn theme(mut self, theme: Theme) -> Self {
+        self.drawing = DrawingParameter::from_theme(theme);
+        self
+    }
+
+    pub fn parse_mode(mut self, parse_mode: ParseMode) -> Self {
+        self.parse_mode = parse_mode;
+        self
+    }
+
+    /// Scans of the logfile which go into the map
+    pub fn select(mut self, selection: ScanSelection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    /// Number of threads for integrating the scans, 0 uses all cores, tiled
+    /// maps are integrated on a single thread
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.parameter.threads = threads;
+        self
+    }
+
+    /// Grow the map in tiles, which allows to build it in a single pass
+    pub fn tiled(mut self, tiled: bool) -> Self {
+        self.parameter.tiled = tiled;
+        self
+    }
+
+    /// Algorithm for finding the cells along a beam
+    pub fn ray_tracer(mut self, ray_tracer: RayTracer) -> Self {
+        self.parameter.ray_tracer = ray_tracer;
+        self
+    }
+
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.parameter.verbose = verbose;
+        self
+    }
+
+    /// Read the selected scans of a CARMEN log or g2o graph, malformed lines
+    /// are dropped in lenient mode
+    pub fn read(&self, filename: impl Into<PathBuf>) -> Result<Vec<RobotLaser>, ParseError> {
+        let parser = datastream::parser_for(filename.into());
+        let scans = parser.parse(self.parse_mode)?.data;
+        Ok(self.selection.apply(scans).collect())
+    }
+
+    /// Build the occupancy map of the scans
+    pub fn build(&self, scans: &[RobotLaser]) -> Result<MapCreator, BuildError> {
+        self.parameter.log_odds.validate()?;
+        if scans.is_empty() {
+            return Err(BuildError::NoScans);
+        }
+        let mut map_creator = MapCreator::new(self.parameter);
+        map_creator.update_boundaries(scans);
+        map_creator.allocate_map();
+        map_creator.integrate_scans(scans);
+        Ok(map_creator)
+    }
+
+    /// Build the occupancy map of the scans and return a drawer showing it
+    pub fn render(&self, scans: &[RobotLaser]) -> Result<MapDrawer, BuildError> {
+        let map_creator = self.build(scans)?;
+        let fmap = map_creator.map.as_ref().unwrap().compute_occupancy_map();
+        Ok(MapDrawer::from_map(
+            map_creator.parameter,
+            self.drawing,
+            &fmap,
+        ))
+    }
+
+    /// Build the occupancy map of a logfile in two passes over the file, or
+    /// in a single pass for a tiled map. The scans are not kept in memory.
+    pub fn render_file(&self, filename: impl Into<PathBuf>) -> Result<MapDrawer, BuildError> {
+        self.parameter.log_odds.validate()?;
+        let parser = datastream::parser_for(filename.into());
+        let mut map_creator = MapCreator::new(self.parameter);
+        let mut error = None;
+        let mut num_scans = 0;
+        if !self.parameter.tiled {
+            let scans = self.stream(parser.as_ref(), &mut error)?;
+            map_creator.update_boundaries(scans.inspect(|_| num_scans += 1));
+            if let Some(e) = error {
+                return Err(e.into());
+            }
+            if num_scans == 0 {
+                return Err(BuildError::NoScans);
+            }
+        }
+        map_creator.allocate_map();
+        let scans = self.stream(parser.as_ref(), &mut error)?;
+        map_creator.integrate_scans(scans.inspect(|_| num_scans += 1));
+        if let Some(e) = error {
+            return Err(e.into());
+        }
+        if num_scans == 0 {
+            return Err(BuildError::NoScans);
+        }
+        let fmap = map_creator.map.as_ref().unwrap().compute_occupancy_map();
+        Ok(MapDrawer::from_map(
+            map_creator.parameter,
+            self.drawing,
+            &fmap,
+        ))
+    }
+
+    /// Stream the selected scans of a file, the stream ends at the first
+    /// error which is stored in `error`
+    fn stream<'a>(
+        &self,
+        parser: &dyn Parser,
+        error: &'a mut Option<ParseError>,
+    ) -> Result<impl Iterator<Item = RobotLaser> + 'a, ParseError> {
+        let scans = parser::skip_malformed(parser.scans()?, self.parse_mode, |_| {});
+        let scans = scans.map_while(|scan| scan.map_err(|e| *error = Some(e)).ok());
+        Ok(self.selection.apply(scans))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datastream::robot_data::test_scan;
+
+    #[test]
+    fn render_scans() {
+        let scans = vec![test_scan(na::Isometry2::identity(), vec![2.; 5])];
+        let builder = MapBuilder::new().resolution(0.5).border(1.);
+        let map_creator = builder.build(&scans).unwrap();
+        let size = map_creator
+            .map
+            .as_ref()
+            .unwrap()
+            .compute_occupancy_map()
+            .map
+            .size;
+        let drawer = builder.render(&scans).unwrap();
+        assert_eq!(drawer.parameter.resolution, 0.5);
+        assert_eq!(
+            [drawer.img.width() as usize, drawer.img.height() as usize],
+            size
+        );
+        assert!(matches!(builder.render(&[]), Err(BuildError::NoScans)));
+    }
+}
@@ -1,7 +1,11 @@
+pub mod beams;
 pub mod boundaries;
 pub mod bresenham;
+pub mod cellmap;
 pub mod floatmap;
 pub mod frequencymap;
 pub mod gridmap;
+pub mod logoddsmap;
 pub mod map_creator;
 pub mod map_creator_parameter;
+pub mod tiledmap;
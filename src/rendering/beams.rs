@@ -0,0 +1,62 @@
+extern crate nalgebra as na;
+
+use crate::datastream::robot_data::RobotLaser;
+
+/// A scan together with the pose and the ranges for integrating it into a map
+#[derive(Clone, Copy)]
+pub struct PosedScan<'a> {
+    pub laser: &'a RobotLaser,
+    pub robot_pose: na::Isometry2<f64>,
+    pub max_range: Option<f64>,
+    pub max_usable_range: Option<f64>,
+}
+
+/// End point of a single beam in world coordinates
+pub struct Beam {
+    pub end: na::Point2<f64>,
+    /// the beam was longer than the usable range and got cropped
+    pub cropped: bool,
+}
+
+/// Iterate over the beams of a scan which are shorter than the max range.
+///
+/// Beams longer than the usable range are cropped to it.
+pub fn beams(
+    laser: &RobotLaser,
+    laser_pose: na::Isometry2<f64>,
+    max_range: Option<f64>,
+    max_usable_range: Option<f64>,
+) -> impl Iterator<Item = Beam> + '_ {
+    let my_max_range = laser
+        .laser_params
+        .max_range
+        .min(max_range.unwrap_or(f64::INFINITY)) as f32;
+    let my_usable_range = max_usable_range.unwrap_or(my_max_range.into()) as f32;
+
+    laser
+        .ranges
+        .iter()
+        .enumerate()
+        .filter(move |&x| *x.1 < my_max_range)
+        .map(move |(i, range)| {
+            let (r, cropped) = if *range > my_usable_range {
+                (my_usable_range, true)
+            } else {
+                (*range, false)
+            };
+            let beam = na::Point2::new(r as f64, 0.);
+            Beam {
+                end: laser_pose * laser.laser_params.beam_isometry(i) * beam,
+                cropped,
+            }
+        })
+}
+
+/// Observation of a cell by a beam
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellObservation {
+    /// the beam ended in the cell
+    Hit,
+    /// the beam passed through the cell
+    Miss,
+}
@@ -28,8 +28,8 @@ pub fn boundaries(
         if r >= max_range {
             continue;
         }
-        if usable_range.is_some() {
-            r = r.min(usable_range.unwrap());
+        if let Some(usable_range) = usable_range {
+            r = r.min(usable_range);
         }
         let point = na::Point2::new(r as f64, 0.);
         let transformed_point = tp * scan.laser_params.beam_isometry(i) * point;
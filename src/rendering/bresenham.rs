@@ -1,3 +1,68 @@
+extern crate nalgebra as na;
+
+use serde::{Deserialize, Serialize};
+
+use super::gridmap::MapTransform;
+
+/// Algorithm for finding the cells along a beam
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RayTracer {
+    /// Bresenham's line between the cells of the start and the end point
+    #[default]
+    Bresenham,
+    /// All the cells the beam passes through based on the exact start and end point
+    Exact,
+}
+
+impl RayTracer {
+    /// Cells along the beam from `start` to `end` given in world coordinates
+    pub fn trace(
+        &self,
+        transform: &MapTransform,
+        start: &na::Vector2<f64>,
+        end: &na::Vector2<f64>,
+    ) -> Ray {
+        match self {
+            RayTracer::Bresenham => {
+                let start = transform.world2map(start);
+                let end = transform.world2map(end);
+                Ray::Bresenham(bresenham(start.x, start.y, end.x, end.y))
+            }
+            RayTracer::Exact => Ray::Exact(grid_traversal(
+                transform.world2map_exact(start),
+                transform.world2map_exact(end),
+            )),
+        }
+    }
+}
+
+/// Cells along a beam found by one of the ray tracers
+pub enum Ray {
+    Bresenham(Bresenham),
+    Exact(GridTraversal),
+}
+
+impl Ray {
+    /// The cell of the end point, which is the last cell of the ray
+    pub fn end(&self) -> [i32; 2] {
+        match self {
+            Ray::Bresenham(r) => r.end,
+            Ray::Exact(r) => r.end,
+        }
+    }
+}
+
+impl Iterator for Ray {
+    type Item = [i32; 2];
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Ray::Bresenham(r) => r.next(),
+            Ray::Exact(r) => r.next(),
+        }
+    }
+}
+
 pub struct Bresenham {
     end: [i32; 2],
     delta: [i32; 2],
@@ -44,6 +109,75 @@ pub fn bresenham(x1: i32, y1: i32, x2: i32, y2: i32) -> Bresenham {
     }
 }
 
+/// Traversal of all the cells a line passes through (Amanatides and Woo)
+pub struct GridTraversal {
+    end: [i32; 2],
+    step: [i32; 2],
+    ///< value of the line parameter at the next cell boundary for each axis
+    t_max: [f64; 2],
+    ///< change of the line parameter for crossing a cell along each axis
+    t_delta: [f64; 2],
+    current: [i32; 2],
+    consumed: bool,
+}
+
+impl Iterator for GridTraversal {
+    type Item = [i32; 2];
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.consumed {
+            return None;
+        }
+        let result = self.current;
+        if self.current == self.end {
+            self.consumed = true;
+        } else {
+            // never step beyond the end cell in case of rounding errors
+            let axis = if self.current[0] == self.end[0] {
+                1
+            } else if self.current[1] == self.end[1] || self.t_max[0] < self.t_max[1] {
+                0
+            } else {
+                1
+            };
+            self.current[axis] += self.step[axis];
+            self.t_max[axis] += self.t_delta[axis];
+        }
+        Some(result)
+    }
+}
+
+/// Cells passed by the line from `start` to `end` given in continuous map
+/// coordinates, i.e., the cell (x, y) covers [x, x + 1) x [y, y + 1)
+pub fn grid_traversal(start: na::Vector2<f64>, end: na::Vector2<f64>) -> GridTraversal {
+    let current = [start.x.floor() as i32, start.y.floor() as i32];
+    let end_cell = [end.x.floor() as i32, end.y.floor() as i32];
+    let mut step = [0; 2];
+    let mut t_max = [f64::INFINITY; 2];
+    let mut t_delta = [f64::INFINITY; 2];
+    for axis in 0..2 {
+        let delta = end[axis] - start[axis];
+        if end_cell[axis] == current[axis] {
+            continue;
+        }
+        step[axis] = if delta > 0. { 1 } else { -1 };
+        t_delta[axis] = 1. / delta.abs();
+        let boundary = if delta > 0. {
+            current[axis] as f64 + 1. - start[axis]
+        } else {
+            start[axis] - current[axis] as f64
+        };
+        t_max[axis] = boundary * t_delta[axis];
+    }
+    GridTraversal {
+        end: end_cell,
+        step,
+        t_max,
+        t_delta,
+        current,
+        consumed: false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -57,4 +191,18 @@ mod tests {
         assert_eq!(line.next().unwrap(), [3, 0]);
         assert_eq!(line.next(), None);
     }
+
+    #[test]
+    fn grid_traversal_passes_all_cells() {
+        // Bresenham jumps diagonally from (0, 0) to (1, 1)
+        let start = na::Vector2::new(0.9, 0.5);
+        let end = na::Vector2::new(1.5, 1.2);
+        let cells: Vec<_> = grid_traversal(start, end).collect();
+        assert_eq!(cells, vec![[0, 0], [1, 0], [1, 1]]);
+
+        let cells: Vec<_> =
+            grid_traversal(na::Vector2::new(2.5, 0.5), na::Vector2::new(-0.5, 0.5)).collect();
+        assert_eq!(cells, vec![[2, 0], [1, 0], [0, 0], [-1, 0]]);
+        assert_eq!(grid_traversal(start, start).count(), 1);
+    }
 }
@@ -0,0 +1,146 @@
+extern crate nalgebra as na;
+
+use super::beams::{beams, CellObservation, PosedScan};
+use super::bresenham::RayTracer;
+use super::floatmap::FloatMap;
+use super::gridmap::{CellRegion, GridMap, GridStorage, MapTransform};
+use super::tiledmap::TiledGridMap;
+
+/// How the cells of a map are updated by the observations of the beams
+pub trait CellModel: Copy + Send + Sync {
+    type Cell: Copy + Send + Sync;
+
+    /// Cell which has not been observed
+    fn unknown_cell(&self) -> Self::Cell;
+    /// A beam ended in the cell
+    fn observe_hit(&self, cell: &mut Self::Cell);
+    /// A beam passed through the cell
+    fn observe_miss(&self, cell: &mut Self::Cell);
+    /// Occupancy of a cell, -1 for cells which have not been observed
+    fn occupancy(&self, cell: &Self::Cell) -> f32;
+
+    fn observe(&self, cell: &mut Self::Cell, observation: CellObservation) {
+        match observation {
+            CellObservation::Hit => self.observe_hit(cell),
+            CellObservation::Miss => self.observe_miss(cell),
+        }
+    }
+}
+
+/// Report the observations of the cells by the beams of a scan and return
+/// the region of the cells it touched
+fn trace_scan(
+    transform: &MapTransform,
+    ray_tracer: RayTracer,
+    scan: &PosedScan,
+    mut observe: impl FnMut([i32; 2], CellObservation),
+) -> CellRegion {
+    let laser_pose = scan.robot_pose * scan.laser.laser_params.laser_pose;
+    let start = transform.world2map(&laser_pose.translation.vector);
+    let mut region = CellRegion::empty();
+    region.extend(&start);
+    for beam in beams(
+        scan.laser,
+        laser_pose,
+        scan.max_range,
+        scan.max_usable_range,
+    ) {
+        let ray = ray_tracer.trace(transform, &laser_pose.translation.vector, &beam.end.coords);
+        let end = ray.end();
+        region.extend(&end.into());
+
+        // the end point of a beam is either a hit or free if the beam got cropped
+        for point in ray.filter(|p| beam.cropped || *p != end) {
+            observe(point, CellObservation::Miss);
+        }
+        if !beam.cropped {
+            observe(end, CellObservation::Hit);
+        }
+    }
+    region
+}
+
+/// Map whose cells of type `M::Cell` are stored in `G` and updated by the model `M`
+pub struct CellMap<M: CellModel, G = GridMap<<M as CellModel>::Cell>> {
+    pub map: G,
+    model: M,
+    ray_tracer: RayTracer,
+}
+
+impl<M: CellModel> CellMap<M> {
+    pub fn new(model: M, size: [usize; 2], resolution: f64, offset: na::Vector2<f64>) -> Self {
+        Self {
+            map: GridMap::new(size, resolution, offset, model.unknown_cell()),
+            model,
+            ray_tracer: RayTracer::default(),
+        }
+    }
+}
+
+impl<M: CellModel> CellMap<M, TiledGridMap<M::Cell>> {
+    /// Map growing with the scans, `border` cells are added around the
+    /// observed cells when computing the occupancy
+    pub fn new_tiled(model: M, resolution: f64, border: usize) -> Self {
+        Self {
+            map: TiledGridMap::new(resolution, border, model.unknown_cell()),
+            model,
+            ray_tracer: RayTracer::default(),
+        }
+    }
+}
+
+impl<M: CellModel, G: GridStorage<M::Cell>> CellMap<M, G> {
+    pub fn with_ray_tracer(mut self, ray_tracer: RayTracer) -> Self {
+        self.ray_tracer = ray_tracer;
+        self
+    }
+}
+
+/// Map into which the scans are integrated, independent of the model of
+/// the cells and of how they are stored
+pub trait OccupancyMap {
+    /// Integrate a single scan and return the region of cells it touched
+    fn integrate_scan(&mut self, scan: &PosedScan) -> CellRegion;
+
+    /// Integrate the scans in order using up to `num_threads` threads, which
+    /// yields the same map as one thread
+    fn integrate_scans(&mut self, scans: &[PosedScan], num_threads: usize);
+
+    /// Occupancy of a single cell, -1 for unknown cells
+    fn occupancy(&self, x: i32, y: i32) -> f32;
+
+    fn compute_occupancy_map(&self) -> FloatMap;
+}
+
+impl<M: CellModel, G: GridStorage<M::Cell>> OccupancyMap for CellMap<M, G> {
+    fn integrate_scan(&mut self, scan: &PosedScan) -> CellRegion {
+        let (model, map) = (self.model, &mut self.map);
+        let transform = map.transform();
+        trace_scan(&transform, self.ray_tracer, scan, |[x, y], observation| {
+            if let Some(c) = map.cell_mut(x, y) {
+                model.observe(c, observation);
+            }
+        })
+    }
+
+    fn integrate_scans(&mut self, scans: &[PosedScan], num_threads: usize) {
+        let (model, ray_tracer) = (self.model, self.ray_tracer);
+        self.map.update_cells(
+            num_threads,
+            scans,
+            |transform, scan, observe| {
+                trace_scan(transform, ray_tracer, scan, observe);
+            },
+            |cell, observation| model.observe(cell, observation),
+        );
+    }
+
+    fn occupancy(&self, x: i32, y: i32) -> f32 {
+        self.map.cell(x, y).map_or(-1., |c| self.model.occupancy(c))
+    }
+
+    fn compute_occupancy_map(&self) -> FloatMap {
+        let map = self.map.to_dense(-1.0f32, |c| self.model.occupancy(c));
+        FloatMap { map }
+    }
+}
@@ -4,16 +4,9 @@ pub struct FloatMap {
     pub map: gridmap::GridMap<f32>,
 }
 
-fn color_for_occ(occ: f32) -> [u8; 4] {
-    let c = (255. - 255. * occ) as u8;
-    match occ {
-        -1. => [140, 170, 238, 255],
-        _ => [c, c, c, 255],
-    }
-}
-
 impl FloatMap {
-    pub fn to_pixels(&self) -> Vec<u8> {
+    /// RGBA data of the map with the top row first
+    pub fn to_pixels<F: Fn(f32) -> [u8; 4]>(&self, color_for_occ: F) -> Vec<u8> {
         let capacity = self.map.size[0] * self.map.size[1] * 4;
         let mut img = Vec::with_capacity(capacity);
 
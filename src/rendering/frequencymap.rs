@@ -1,93 +1,42 @@
-extern crate nalgebra as na;
-
-use core::f32;
-use std::iter::zip;
-
-use crate::datastream::robot_data::RobotLaser;
-
-use super::bresenham::bresenham;
-use super::{floatmap::FloatMap, gridmap};
+use super::cellmap::{CellMap, CellModel};
+use super::gridmap::GridMap;
 
 #[derive(Debug, Copy, Clone)]
 pub struct FrequencyMapCell {
     hits: i32,
+    ///< number of beams which ended in or passed through the cell
     misses: i32,
 }
 
-pub struct FrequencyMap {
-    pub map: gridmap::GridMap<FrequencyMapCell>,
-}
-
-impl FrequencyMap {
-    pub fn new(size: [usize; 2], resolution: f64, offset: na::Vector2<f64>) -> Self {
-        let default_cell = FrequencyMapCell { hits: 0, misses: 0 };
-        let map = gridmap::GridMap::new(size, resolution, offset, default_cell);
-        Self { map }
-    }
+/// Model counting how often the beams end in or pass through each cell
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrequencyModel;
 
-    pub fn integrate_scan(
-        &mut self,
-        laser: &RobotLaser,
-        robot_pose: na::Isometry2<f64>,
-        max_range: Option<f64>,
-        max_usable_range: Option<f64>,
-        gain: Option<i32>,
-    ) {
-        let my_max_range = laser
-            .laser_params
-            .max_range
-            .min(max_range.unwrap_or(f64::INFINITY)) as f32;
-        let my_usable_range = max_usable_range.unwrap_or(my_max_range.into()) as f32;
+impl CellModel for FrequencyModel {
+    type Cell = FrequencyMapCell;
 
-        let laser_pose = robot_pose * laser.laser_params.laser_pose;
-        let start = self.map.world2map(&laser_pose.translation.vector);
-        for (i, range) in laser
-            .ranges
-            .iter()
-            .enumerate()
-            .filter(|&x| *x.1 < my_max_range)
-        {
-            let (r, cropped) = if *range > my_usable_range {
-                (my_usable_range, true)
-            } else {
-                (*range, false)
-            };
-            let beam = na::Point2::new(r as f64, 0.);
-            let beam_end_point = laser_pose * laser.laser_params.beam_isometry(i) * beam;
-            let end = self.map.world2map(&beam_end_point.coords);
+    fn unknown_cell(&self) -> FrequencyMapCell {
+        FrequencyMapCell { hits: 0, misses: 0 }
+    }
 
-            let line = bresenham(start.x, start.y, end.x, end.y);
-            for point in line {
-                match self.map.cell_mut(point[0], point[1]) {
-                    Some(c) => c.misses += gain.unwrap_or(1),
-                    None => continue,
-                }
-            }
-            if cropped {
-                continue;
-            }
-            match self.map.cell_mut(end[0], end[1]) {
-                Some(c) => c.hits += gain.unwrap_or(1),
-                None => continue,
-            }
-        }
+    fn observe_hit(&self, cell: &mut FrequencyMapCell) {
+        cell.hits += 1;
+        cell.misses += 1;
     }
 
-    pub fn compute_occupancy_map(&self) -> FloatMap {
-        let default_cell = -1.0f32;
-        let mut map = gridmap::GridMap::new(
-            self.map.size,
-            self.map.resolution,
-            self.map.offset,
-            default_cell,
-        );
+    fn observe_miss(&self, cell: &mut FrequencyMapCell) {
+        cell.misses += 1;
+    }
 
-        for (hits_misses, occupancy) in
-            zip(self.map.cells(), map.cells_mut()).filter(|x| x.0.misses > 0)
-        {
-            *occupancy = hits_misses.hits as f32 / hits_misses.misses as f32;
+    /// Ratio of hits and misses, -1 for cells which have not been observed
+    fn occupancy(&self, cell: &FrequencyMapCell) -> f32 {
+        if cell.misses > 0 {
+            cell.hits as f32 / cell.misses as f32
+        } else {
+            -1.
         }
-
-        FloatMap { map }
     }
 }
+
+/// Map counting how often the beams end in or pass through each cell
+pub type FrequencyMap<G = GridMap<FrequencyMapCell>> = CellMap<FrequencyModel, G>;
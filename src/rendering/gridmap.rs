@@ -1,5 +1,69 @@
 extern crate nalgebra as na;
 
+use std::iter::zip;
+use std::ops::Range;
+
+/// Rectangular region of cells given by its inclusive min and max corner
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellRegion {
+    pub min: na::Vector2<i32>,
+    pub max: na::Vector2<i32>,
+}
+
+impl CellRegion {
+    pub fn empty() -> Self {
+        Self {
+            min: na::Vector2::new(i32::MAX, i32::MAX),
+            max: na::Vector2::new(i32::MIN, i32::MIN),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y
+    }
+
+    pub fn extend(&mut self, cell: &na::Vector2<i32>) {
+        self.min = self.min.inf(cell);
+        self.max = self.max.sup(cell);
+    }
+}
+
+/// Transformation between world coordinates and the cells of a map.
+///
+/// The cell (x, y) covers the area from `offset + (x, y) * resolution` to
+/// `offset + (x + 1, y + 1) * resolution`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MapTransform {
+    pub resolution: f64,
+    pub offset: na::Vector2<f64>,
+}
+
+impl MapTransform {
+    pub fn new(resolution: f64, offset: na::Vector2<f64>) -> Self {
+        Self { resolution, offset }
+    }
+
+    /// Cell containing a point given in world coordinates
+    pub fn world2map(&self, wp: &na::Vector2<f64>) -> na::Vector2<i32> {
+        self.world2map_exact(wp).map(|c| c.floor() as i32)
+    }
+
+    /// Continuous map coordinates of a point given in world coordinates
+    pub fn world2map_exact(&self, wp: &na::Vector2<f64>) -> na::Vector2<f64> {
+        (wp - self.offset) / self.resolution
+    }
+
+    /// World coordinates of continuous map coordinates
+    pub fn map2world_exact(&self, map_point: &na::Vector2<f64>) -> na::Vector2<f64> {
+        map_point * self.resolution + self.offset
+    }
+
+    /// Center of a cell in world coordinates
+    pub fn map2world(&self, cell: &na::Vector2<i32>) -> na::Vector2<f64> {
+        (cell.cast::<f64>().add_scalar(0.5)) * self.resolution + self.offset
+    }
+}
+
 pub struct GridMap<T> {
     pub resolution: f64,
     pub offset: na::Vector2<f64>,
@@ -47,12 +111,308 @@ impl<T: Copy> GridMap<T> {
         }
     }
 
+    pub fn transform(&self) -> MapTransform {
+        MapTransform::new(self.resolution, self.offset)
+    }
+
     pub fn world2map(&self, wp: &na::Vector2<f64>) -> na::Vector2<i32> {
-        let map_point = (wp - self.offset) / self.resolution;
-        na::Vector2::new(map_point.x as i32, map_point.y as i32)
+        self.transform().world2map(wp)
+    }
+
+    pub fn map2world(&self, cell: &na::Vector2<i32>) -> na::Vector2<f64> {
+        self.transform().map2world(cell)
     }
 
     pub fn is_inside(&self, x: i32, y: i32) -> bool {
-        x > 0 && y > 0 && (x as usize) < self.size[0] && (y as usize) < self.size[1]
+        is_inside(self.size, x, y)
+    }
+
+    /// Number of rows of each stripe when splitting the map into `num` stripes
+    fn rows_per_stripe(&self, num: usize) -> usize {
+        self.size[1].div_ceil(num.max(1)).max(1)
+    }
+
+    /// Split the map into at most `num` stripes of consecutive rows
+    pub fn stripes_mut(&mut self, num: usize) -> Vec<GridMapStripe<'_, T>> {
+        let width = self.size[0].max(1);
+        let rows_per_stripe = self.rows_per_stripe(num);
+        self.grid
+            .chunks_mut(rows_per_stripe * width)
+            .enumerate()
+            .map(|(i, cells)| {
+                let first_row = (i * rows_per_stripe) as i32;
+                GridMapStripe {
+                    transform: MapTransform::new(self.resolution, self.offset),
+                    size: self.size,
+                    rows: first_row..first_row + (cells.len() / width) as i32,
+                    cells,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Number of items traced by each thread of `GridMap::update_parallel`
+/// before their updates are applied, bounds the memory of the buffered updates
+const ITEMS_PER_THREAD: usize = 16;
+
+impl<T: Copy + Send> GridMap<T> {
+    /// Apply the cell updates of all the items using `num_threads` threads.
+    ///
+    /// `trace` reports the updates of an item by passing a cell and an update
+    /// to its callback, `apply` changes a cell by an update. The threads trace
+    /// consecutive chunks of the items once and buffer the updates per stripe
+    /// of the map, then each stripe is updated by a single thread with the
+    /// buffers of the chunks in order. Thus the cells receive their updates in
+    /// the same order as if the items were traced and applied on one thread.
+    pub fn update_parallel<I, U, F, A>(
+        &mut self,
+        num_threads: usize,
+        items: &[I],
+        trace: F,
+        apply: A,
+    ) where
+        I: Sync,
+        U: Copy + Send + Sync,
+        F: Fn(&MapTransform, &I, &mut dyn FnMut([i32; 2], U)) + Sync,
+        A: Fn(&mut T, U) + Sync,
+    {
+        let num_threads = num_threads.max(1);
+        let (transform, size) = (self.transform(), self.size);
+        let rows_per_stripe = self.rows_per_stripe(num_threads);
+        let num_stripes = size[1].div_ceil(rows_per_stripe);
+        // updates by tracing thread and by stripe given by the index of the cell
+        let mut buffers: Vec<Vec<Vec<(usize, U)>>> =
+            vec![vec![Vec::new(); num_stripes]; num_threads];
+        let (trace, apply) = (&trace, &apply);
+        for round in items.chunks(num_threads * ITEMS_PER_THREAD) {
+            let chunk_size = round.len().div_ceil(num_threads);
+            std::thread::scope(|scope| {
+                for (chunk, stripes) in round.chunks(chunk_size).zip(buffers.iter_mut()) {
+                    scope.spawn(move || {
+                        for item in chunk {
+                            trace(&transform, item, &mut |[x, y], update| {
+                                if is_inside(size, x, y) {
+                                    let index = y as usize * size[0] + x as usize;
+                                    stripes[y as usize / rows_per_stripe].push((index, update));
+                                }
+                            });
+                        }
+                    });
+                }
+            });
+            let buffers_ref = &buffers;
+            std::thread::scope(|scope| {
+                for (i, stripe) in self.stripes_mut(num_threads).into_iter().enumerate() {
+                    scope.spawn(move || {
+                        let first = stripe.rows.start as usize * size[0];
+                        for stripes in buffers_ref {
+                            for (index, update) in stripes[i].iter() {
+                                apply(&mut stripe.cells[index - first], *update);
+                            }
+                        }
+                    });
+                }
+            });
+            buffers.iter_mut().flatten().for_each(Vec::clear);
+        }
+    }
+}
+
+/// Horizontal stripe of a grid map which only allows to modify its own rows
+pub struct GridMapStripe<'a, T> {
+    transform: MapTransform,
+    size: [usize; 2],
+    rows: Range<i32>,
+    cells: &'a mut [T],
+}
+
+/// Access to the cells of a grid map or a part of it
+pub trait GridAccess<T> {
+    /// Transformation between world coordinates and the cells
+    fn transform(&self) -> MapTransform;
+    fn cell_mut(&mut self, x: i32, y: i32) -> Option<&mut T>;
+}
+
+/// Storage of all the cells of a map
+pub trait GridStorage<T>: GridAccess<T> {
+    fn cell(&self, x: i32, y: i32) -> Option<&T>;
+
+    /// Convert the cells into a dense map
+    fn to_dense<U: Copy, F: Fn(&T) -> U>(&self, unknown_cell: U, convert: F) -> GridMap<U>;
+
+    /// Apply the cell updates of all the items in order, see
+    /// `GridMap::update_parallel`. Storages which cannot be updated in
+    /// parallel use a single thread.
+    fn update_cells<I, U, F, A>(&mut self, _num_threads: usize, items: &[I], trace: F, apply: A)
+    where
+        I: Sync,
+        U: Copy + Send + Sync,
+        F: Fn(&MapTransform, &I, &mut dyn FnMut([i32; 2], U)) + Sync,
+        A: Fn(&mut T, U) + Sync,
+    {
+        update_serial(self, items, trace, apply);
+    }
+}
+
+/// Trace the items and apply their cell updates in order on a single thread
+fn update_serial<T, G, I, U, F, A>(map: &mut G, items: &[I], trace: F, apply: A)
+where
+    G: GridAccess<T> + ?Sized,
+    F: Fn(&MapTransform, &I, &mut dyn FnMut([i32; 2], U)),
+    A: Fn(&mut T, U),
+{
+    let transform = map.transform();
+    for item in items {
+        trace(&transform, item, &mut |[x, y], update| {
+            if let Some(c) = map.cell_mut(x, y) {
+                apply(c, update);
+            }
+        });
+    }
+}
+
+impl<T: Copy + Send> GridStorage<T> for GridMap<T> {
+    fn cell(&self, x: i32, y: i32) -> Option<&T> {
+        GridMap::cell(self, x, y)
+    }
+
+    fn update_cells<I, U, F, A>(&mut self, num_threads: usize, items: &[I], trace: F, apply: A)
+    where
+        I: Sync,
+        U: Copy + Send + Sync,
+        F: Fn(&MapTransform, &I, &mut dyn FnMut([i32; 2], U)) + Sync,
+        A: Fn(&mut T, U) + Sync,
+    {
+        if num_threads > 1 {
+            self.update_parallel(num_threads, items, trace, apply);
+        } else {
+            update_serial(self, items, trace, apply);
+        }
+    }
+
+    fn to_dense<U: Copy, F: Fn(&T) -> U>(&self, unknown_cell: U, convert: F) -> GridMap<U> {
+        let mut map = GridMap::new(self.size, self.resolution, self.offset, unknown_cell);
+        for (cell, converted) in zip(self.cells(), map.cells_mut()) {
+            *converted = convert(cell);
+        }
+        map
+    }
+}
+
+impl<T: Copy> GridAccess<T> for GridMap<T> {
+    fn transform(&self) -> MapTransform {
+        GridMap::transform(self)
+    }
+
+    fn cell_mut(&mut self, x: i32, y: i32) -> Option<&mut T> {
+        GridMap::cell_mut(self, x, y)
+    }
+}
+
+impl<T> GridAccess<T> for GridMapStripe<'_, T> {
+    fn transform(&self) -> MapTransform {
+        self.transform
+    }
+
+    fn cell_mut(&mut self, x: i32, y: i32) -> Option<&mut T> {
+        if !is_inside(self.size, x, y) || !self.rows.contains(&y) {
+            None
+        } else {
+            let row = (y - self.rows.start) as usize;
+            self.cells.get_mut(row * self.size[0] + x as usize)
+        }
+    }
+}
+
+fn is_inside(size: [usize; 2], x: i32, y: i32) -> bool {
+    x >= 0 && y >= 0 && (x as usize) < size[0] && (y as usize) < size[1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn negative_and_border_cells() {
+        let map = GridMap::new([3, 2], 0.1, na::Vector2::new(-1., 2.), 0);
+        assert_eq!(
+            map.world2map(&na::Vector2::new(-1.05, 1.95)),
+            na::Vector2::new(-1, -1)
+        );
+        assert_eq!(
+            map.world2map(&na::Vector2::new(-0.95, 2.05)),
+            na::Vector2::new(0, 0)
+        );
+        assert!(map.cell(0, 0).is_some());
+        assert!(map.cell(2, 1).is_some());
+        assert!(map.cell(-1, 0).is_none());
+        assert!(map.cell(3, 1).is_none());
+    }
+
+    #[test]
+    fn parallel_updates_trace_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // lines through the map and beyond its border, the update depends on
+        // the order in which a cell receives the updates
+        let lines: Vec<[i32; 4]> = (0..100)
+            .map(|i| [i % 7 - 2, i % 11, (i * 3) % 13, 12 - i % 17])
+            .collect();
+        let traced = AtomicUsize::new(0);
+        let trace = |_: &MapTransform, l: &[i32; 4], update: &mut dyn FnMut([i32; 2], u32)| {
+            traced.fetch_add(1, Ordering::Relaxed);
+            for cell in crate::rendering::bresenham::bresenham(l[0], l[1], l[2], l[3]) {
+                update(cell, l[0] as u32);
+            }
+        };
+        let apply = |cell: &mut u32, u: u32| *cell = cell.wrapping_mul(31).wrapping_add(u);
+
+        let mut serial = GridMap::new([9, 10], 0.1, na::Vector2::zeros(), 1u32);
+        for l in lines.iter() {
+            trace(&serial.transform(), l, &mut |[x, y], u| {
+                if let Some(c) = serial.cell_mut(x, y) {
+                    apply(c, u);
+                }
+            });
+        }
+        for num_threads in [2, 3, 8] {
+            traced.store(0, Ordering::Relaxed);
+            let mut map = GridMap::new([9, 10], 0.1, na::Vector2::zeros(), 1u32);
+            map.update_parallel(num_threads, &lines, trace, apply);
+            assert_eq!(traced.load(Ordering::Relaxed), lines.len());
+            assert!(map.cells().eq(serial.cells()));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn cell_round_trip(
+            x in -100_000i32..100_000,
+            y in -100_000i32..100_000,
+            resolution in 0.005f64..2.,
+            ox in -1000f64..1000.,
+            oy in -1000f64..1000.,
+        ) {
+            let transform = MapTransform::new(resolution, na::Vector2::new(ox, oy));
+            let cell = na::Vector2::new(x, y);
+            prop_assert_eq!(transform.world2map(&transform.map2world(&cell)), cell);
+        }
+
+        #[test]
+        fn point_within_cell(
+            px in -1000f64..1000.,
+            py in -1000f64..1000.,
+            resolution in 0.005f64..2.,
+            ox in -1000f64..1000.,
+            oy in -1000f64..1000.,
+        ) {
+            let transform = MapTransform::new(resolution, na::Vector2::new(ox, oy));
+            let point = na::Vector2::new(px, py);
+            let center = transform.map2world(&transform.world2map(&point));
+            let max_error = resolution / 2. * (1. + 1e-9);
+            prop_assert!((center - point).abs().max() <= max_error);
+        }
     }
 }
@@ -0,0 +1,230 @@
+extern crate nalgebra as na;
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::cellmap::{CellMap, CellModel};
+use super::gridmap::GridMap;
+
+/// Inverse sensor model of the log-odds map given as probabilities
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogOddsParameter {
+    ///< probability of a cell being occupied if a beam ends in it
+    pub prob_hit: f64,
+    ///< probability of a cell being occupied if a beam passes through it
+    pub prob_miss: f64,
+    ///< lower bound for the occupancy probability of a cell
+    pub clamp_min: f64,
+    ///< upper bound for the occupancy probability of a cell
+    pub clamp_max: f64,
+}
+
+impl Default for LogOddsParameter {
+    fn default() -> Self {
+        Self {
+            prob_hit: 0.7,
+            prob_miss: 0.4,
+            clamp_min: 0.12,
+            clamp_max: 0.97,
+        }
+    }
+}
+
+/// Inverse sensor model which cannot be converted into log-odds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogOddsError {
+    /// A probability is not within (0, 1), its log-odds are infinite
+    Probability { name: &'static str, value: f64 },
+    /// A hit does not raise or a miss does not lower the occupancy
+    Model { prob_hit: f64, prob_miss: f64 },
+    /// The lower bound is not below the upper bound
+    Clamp { clamp_min: f64, clamp_max: f64 },
+}
+
+impl fmt::Display for LogOddsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogOddsError::Probability { name, value } => {
+                write!(f, "{} = {} has to be within (0, 1)", name, value)
+            }
+            LogOddsError::Model {
+                prob_hit,
+                prob_miss,
+            } => write!(
+                f,
+                "prob_miss = {} has to be below 0.5 and prob_hit = {} above 0.5",
+                prob_miss, prob_hit
+            ),
+            LogOddsError::Clamp {
+                clamp_min,
+                clamp_max,
+            } => write!(
+                f,
+                "clamp_min = {} has to be below clamp_max = {}",
+                clamp_min, clamp_max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LogOddsError {}
+
+impl LogOddsParameter {
+    /// Check that all probabilities are within (0, 1), that
+    /// `prob_miss < 0.5 < prob_hit` and that `clamp_min < clamp_max`
+    pub fn validate(&self) -> Result<(), LogOddsError> {
+        let probabilities = [
+            ("prob_hit", self.prob_hit),
+            ("prob_miss", self.prob_miss),
+            ("clamp_min", self.clamp_min),
+            ("clamp_max", self.clamp_max),
+        ];
+        for (name, value) in probabilities {
+            if !(value > 0. && value < 1.) {
+                return Err(LogOddsError::Probability { name, value });
+            }
+        }
+        if !(self.prob_miss < 0.5 && self.prob_hit > 0.5) {
+            return Err(LogOddsError::Model {
+                prob_hit: self.prob_hit,
+                prob_miss: self.prob_miss,
+            });
+        }
+        if self.clamp_min >= self.clamp_max {
+            return Err(LogOddsError::Clamp {
+                clamp_min: self.clamp_min,
+                clamp_max: self.clamp_max,
+            });
+        }
+        Ok(())
+    }
+}
+
+fn log_odds(probability: f64) -> f32 {
+    (probability / (1. - probability)).ln() as f32
+}
+
+fn probability(log_odds: f32) -> f32 {
+    1. - 1. / (1. + log_odds.exp())
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct LogOddsMapCell {
+    log_odds: f32,
+    observed: bool,
+}
+
+/// Inverse sensor model converted into log-odds
+#[derive(Debug, Clone, Copy)]
+pub struct LogOddsModel {
+    log_hit: f32,
+    log_miss: f32,
+    log_min: f32,
+    log_max: f32,
+}
+
+impl LogOddsModel {
+    pub fn new(parameter: &LogOddsParameter) -> Self {
+        Self {
+            log_hit: log_odds(parameter.prob_hit),
+            log_miss: log_odds(parameter.prob_miss),
+            log_min: log_odds(parameter.clamp_min),
+            log_max: log_odds(parameter.clamp_max),
+        }
+    }
+
+    fn update(&self, cell: &mut LogOddsMapCell, delta: f32) {
+        cell.log_odds = (cell.log_odds + delta).clamp(self.log_min, self.log_max);
+        cell.observed = true;
+    }
+}
+
+impl CellModel for LogOddsModel {
+    type Cell = LogOddsMapCell;
+
+    fn unknown_cell(&self) -> LogOddsMapCell {
+        LogOddsMapCell {
+            log_odds: 0.,
+            observed: false,
+        }
+    }
+
+    fn observe_hit(&self, cell: &mut LogOddsMapCell) {
+        self.update(cell, self.log_hit);
+    }
+
+    fn observe_miss(&self, cell: &mut LogOddsMapCell) {
+        self.update(cell, self.log_miss);
+    }
+
+    /// Occupancy probability, -1 for cells which have not been observed
+    fn occupancy(&self, cell: &LogOddsMapCell) -> f32 {
+        if cell.observed {
+            probability(cell.log_odds)
+        } else {
+            -1.
+        }
+    }
+}
+
+/// Bayesian occupancy grid storing the log-odds of each cell
+pub type LogOddsMap<G = GridMap<LogOddsMapCell>> = CellMap<LogOddsModel, G>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datastream::robot_data::{test_scan, LaserParameters, RobotLaser};
+    use crate::rendering::beams::PosedScan;
+    use crate::rendering::cellmap::OccupancyMap;
+
+    #[test]
+    fn single_beam() {
+        let laser = RobotLaser {
+            laser_params: LaserParameters::new(na::Isometry2::identity(), 0., 0.1, 20.),
+            ..test_scan(na::Isometry2::identity(), vec![0.55])
+        };
+        let offset = na::Vector2::new(0., -0.5);
+        let model = LogOddsModel::new(&LogOddsParameter::default());
+        let mut lmap = LogOddsMap::new(model, [10, 10], 0.1, offset);
+        let scan = PosedScan {
+            laser: &laser,
+            robot_pose: na::Isometry2::identity(),
+            max_range: None,
+            max_usable_range: None,
+        };
+        for _ in 0..10 {
+            lmap.integrate_scan(&scan);
+        }
+        let occ = lmap.compute_occupancy_map();
+        let cell = |x| *occ.map.cell(x, 5).unwrap();
+        assert!((cell(5) - 0.97).abs() < 1e-5);
+        assert!((cell(3) - 0.12).abs() < 1e-5);
+        assert_eq!(cell(7), -1.);
+    }
+
+    #[test]
+    fn validate_parameter() {
+        let valid = LogOddsParameter::default();
+        assert_eq!(valid.validate(), Ok(()));
+        let invalid = |parameter: LogOddsParameter| parameter.validate().is_err();
+        assert!(invalid(LogOddsParameter {
+            prob_hit: 1.,
+            ..valid
+        }));
+        assert!(invalid(LogOddsParameter {
+            clamp_min: f64::NAN,
+            ..valid
+        }));
+        assert!(invalid(LogOddsParameter {
+            prob_miss: 0.6,
+            ..valid
+        }));
+        assert!(invalid(LogOddsParameter {
+            clamp_min: 0.9,
+            clamp_max: 0.1,
+            ..valid
+        }));
+    }
+}
@@ -1,18 +1,27 @@
 extern crate nalgebra as na;
 
+use std::borrow::Borrow;
+use std::fmt;
 use std::io::Write;
 
 use crate::datastream::robot_data::RobotLaser;
 
+use super::beams::PosedScan;
 use super::boundaries::boundaries;
-use super::frequencymap::FrequencyMap;
-use super::map_creator_parameter::MapCreatorParameter;
+use super::cellmap::{CellMap, CellModel, OccupancyMap};
+use super::frequencymap::FrequencyModel;
+use super::gridmap::{CellRegion, MapTransform};
+use super::logoddsmap::LogOddsModel;
+use super::map_creator_parameter::{MapCreatorParameter, MapModel};
+
+/// Number of scans integrated at once by `MapCreator::integrate_scans`
+const SCAN_BATCH_SIZE: usize = 1024;
 
 pub struct MapCreator {
     pub parameter: MapCreatorParameter,
     pub boundaries_min: na::Vector2<f64>,
     pub boundaries_max: na::Vector2<f64>,
-    pub fmap: Option<FrequencyMap>,
+    pub map: Option<Box<dyn OccupancyMap>>,
 }
 
 impl MapCreator {
@@ -21,16 +30,18 @@ impl MapCreator {
             parameter,
             boundaries_min: na::Vector2::new(f64::INFINITY, f64::INFINITY),
             boundaries_max: na::Vector2::new(f64::NEG_INFINITY, f64::NEG_INFINITY),
-            fmap: None,
+            map: None,
         }
     }
 
-    pub fn update_boundaries(&mut self, scans: &[RobotLaser]) {
-        for rl in scans.iter() {
-            if self.parameter.zero_first_pose {
-                self.parameter.zero_first_pose = false;
-                self.parameter.offset = rl.odom_pose.inverse();
-            }
+    pub fn update_boundaries<I>(&mut self, scans: I)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<RobotLaser>,
+    {
+        for rl in scans {
+            let rl = rl.borrow();
+            self.apply_zero_first_pose(rl);
             let my_max_range = self.parameter.max_range.min(rl.laser_params.max_range);
             let my_usable_range = self
                 .parameter
@@ -47,69 +58,197 @@ impl MapCreator {
         }
     }
 
-    pub fn integrate_scans(&mut self, scans: &[RobotLaser]) {
-        if self.fmap.is_none() {
-            panic!("Called integrate_scans without an allocated map");
+    /// Print verbose information to stdout or to stderr
+    fn info(&self, message: fmt::Arguments) {
+        if !self.parameter.verbose {
+            return;
+        }
+        if self.parameter.verbose_stderr {
+            eprint!("{}", message);
+        } else {
+            print!("{}", message);
+            let _ = std::io::stdout().flush();
         }
+    }
 
-        let map = self.fmap.as_mut().unwrap();
+    /// Move the first scan into the origin if requested
+    fn apply_zero_first_pose(&mut self, rl: &RobotLaser) {
+        if self.parameter.zero_first_pose {
+            self.parameter.zero_first_pose = false;
+            self.parameter.offset = rl.odom_pose.inverse();
+        }
+    }
 
-        if self.parameter.verbose {
-            print!("Integrating scans ... ");
-            let _ = std::io::stdout().flush();
+    /// The pose and the ranges for integrating a scan
+    fn posed_scan<'a>(&self, rl: &'a RobotLaser) -> PosedScan<'a> {
+        let my_max_range = self.parameter.max_range.min(rl.laser_params.max_range);
+        let my_usable_range = self
+            .parameter
+            .max_usable_range
+            .min(rl.laser_params.max_range);
+        PosedScan {
+            laser: rl,
+            robot_pose: self.parameter.offset * rl.odom_pose,
+            max_range: Some(my_max_range),
+            max_usable_range: Some(my_usable_range),
         }
-        for rl in scans.iter() {
-            let my_max_range = self.parameter.max_range.min(rl.laser_params.max_range);
-            let my_usable_range = self
-                .parameter
-                .max_usable_range
-                .min(rl.laser_params.max_range);
+    }
 
-            map.integrate_scan(
-                rl,
-                self.parameter.offset * rl.odom_pose,
-                Some(my_max_range),
-                Some(my_usable_range),
-                None,
-            );
+    /// Integrate a single scan and return the region of cells it touched
+    pub fn integrate_scan(&mut self, rl: &RobotLaser) -> CellRegion {
+        self.apply_zero_first_pose(rl);
+        let scan = self.posed_scan(rl);
+        let Some(map) = self.map.as_mut() else {
+            panic!("Called integrate_scan without an allocated map");
+        };
+        map.integrate_scan(&scan)
+    }
+
+    /// Integrate the scans in batches, only a batch is kept in memory when
+    /// the scans are streamed from a file
+    pub fn integrate_scans<I>(&mut self, scans: I)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<RobotLaser>,
+    {
+        if self.map.is_none() {
+            panic!("Called integrate_scans without an allocated map");
         }
-        if self.parameter.verbose {
-            println!("done.");
+
+        // a tiled map grows while integrating and is updated by a single thread
+        let num_threads = match self.parameter.threads {
+            _ if self.parameter.tiled => 1,
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+        self.info(format_args!(
+            "Integrating scans ({} threads) ... ",
+            num_threads
+        ));
+        let mut scans = scans.into_iter();
+        loop {
+            let batch: Vec<I::Item> = scans.by_ref().take(SCAN_BATCH_SIZE).collect();
+            let Some(first) = batch.first() else {
+                break;
+            };
+            self.apply_zero_first_pose(first.borrow());
+            let posed_scans: Vec<PosedScan> = batch
+                .iter()
+                .map(|rl| self.posed_scan(rl.borrow()))
+                .collect();
+            self.map
+                .as_mut()
+                .unwrap()
+                .integrate_scans(&posed_scans, num_threads);
         }
+        self.info(format_args!("done.\n"));
     }
 
+    /// Allocate the map, a tiled map grows with the scans and does not
+    /// require the boundaries
     pub fn allocate_map(&mut self) {
-        if self.parameter.verbose {
-            println!(
-                "Boundaries: {:.3} {:.3} -> {:.3} {:.3}",
-                self.boundaries_min.x,
-                self.boundaries_min.y,
-                self.boundaries_max.x,
-                self.boundaries_max.y
-            );
+        if self.parameter.tiled {
+            self.allocate_tiled_map();
+            return;
         }
+        self.info(format_args!(
+            "Boundaries: {:.3} {:.3} -> {:.3} {:.3}\n",
+            self.boundaries_min.x,
+            self.boundaries_min.y,
+            self.boundaries_max.x,
+            self.boundaries_max.y
+        ));
         let border = na::Vector2::new(self.parameter.border, self.parameter.border);
         let boundaries_min = self.boundaries_min - border;
         let boundaries_max = self.boundaries_max + border;
-        if self.parameter.verbose {
-            println!(
-                "Extended Boundaries: {:.3} {:.3} -> {:.3} {:.3}",
-                boundaries_min.x, boundaries_min.y, boundaries_max.x, boundaries_max.y
-            );
-        }
+        self.info(format_args!(
+            "Extended Boundaries: {:.3} {:.3} -> {:.3} {:.3}\n",
+            boundaries_min.x, boundaries_min.y, boundaries_max.x, boundaries_max.y
+        ));
 
-        let dsize = boundaries_max - boundaries_min;
-        let isize = (dsize / self.parameter.resolution)
-            .try_cast::<usize>()
-            .unwrap();
+        // the map has to contain the cell of the max boundary
+        let resolution = self.parameter.resolution;
+        let transform = MapTransform::new(resolution, boundaries_min);
+        let max_cell = transform.world2map(&boundaries_max);
+        let size = [max_cell.x + 1, max_cell.y + 1].map(|s| s.max(0) as usize);
 
-        if self.parameter.verbose {
-            println!("Allocating map size {} x {}", isize.x, isize.y)
-        }
-        self.fmap = Some(FrequencyMap::new(
-            [isize.x, isize.y],
-            self.parameter.resolution,
-            boundaries_min,
+        self.info(format_args!(
+            "Allocating map size {} x {}\n",
+            size[0], size[1]
         ));
+        self.map = Some(match self.parameter.map_model {
+            MapModel::Frequency => self.dense_map(FrequencyModel, size, boundaries_min),
+            MapModel::LogOdds => {
+                let model = LogOddsModel::new(&self.parameter.log_odds);
+                self.dense_map(model, size, boundaries_min)
+            }
+        });
+    }
+
+    fn dense_map<M: CellModel + 'static>(
+        &self,
+        model: M,
+        size: [usize; 2],
+        offset: na::Vector2<f64>,
+    ) -> Box<dyn OccupancyMap> {
+        let map = CellMap::new(model, size, self.parameter.resolution, offset);
+        Box::new(map.with_ray_tracer(self.parameter.ray_tracer))
+    }
+
+    fn allocate_tiled_map(&mut self) {
+        self.info(format_args!("Allocating tiled map\n"));
+        self.map = Some(match self.parameter.map_model {
+            MapModel::Frequency => self.tiled_map(FrequencyModel),
+            MapModel::LogOdds => self.tiled_map(LogOddsModel::new(&self.parameter.log_odds)),
+        });
+    }
+
+    fn tiled_map<M: CellModel + 'static>(&self, model: M) -> Box<dyn OccupancyMap> {
+        let resolution = self.parameter.resolution;
+        let border = (self.parameter.border / resolution).ceil() as usize;
+        let map = CellMap::new_tiled(model, resolution, border);
+        Box::new(map.with_ray_tracer(self.parameter.ray_tracer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datastream::robot_data::{test_scan, LaserParameters};
+
+    #[test]
+    fn parallel_matches_serial() {
+        let scans: Vec<RobotLaser> = (0..20)
+            .map(|i| {
+                let i = i as f64;
+                let pose = na::Isometry2::new(na::Vector2::new(0.3 * i, 0.1 * i), 0.2 * i);
+                let ranges = (0..61)
+                    .map(|j| 1. + ((i + j as f64) * 0.7).sin().abs() as f32 * 4.)
+                    .collect();
+                RobotLaser {
+                    laser_params: LaserParameters::new(na::Isometry2::identity(), -1.5, 0.05, 20.),
+                    ..test_scan(pose, ranges)
+                }
+            })
+            .collect();
+        for map_model in [MapModel::Frequency, MapModel::LogOdds] {
+            let occupancy = |threads| {
+                let mut map_creator = MapCreator::new(MapCreatorParameter {
+                    map_model,
+                    threads,
+                    max_usable_range: 3.,
+                    ..Default::default()
+                });
+                map_creator.update_boundaries(&scans);
+                map_creator.allocate_map();
+                map_creator.integrate_scans(&scans);
+                let fmap = map_creator.map.unwrap().compute_occupancy_map();
+                fmap.map.cells().map(|c| c.to_bits()).collect::<Vec<_>>()
+            };
+            let serial = occupancy(1);
+            assert!(serial.iter().any(|c| f32::from_bits(*c) > 0.));
+            assert_eq!(serial, occupancy(3));
+            assert_eq!(serial, occupancy(7));
+        }
     }
 }
@@ -1,6 +1,23 @@
 extern crate nalgebra as na;
 
-#[derive(Debug, Clone, Copy)]
+use serde::{Deserialize, Serialize};
+
+use super::bresenham::RayTracer;
+use super::logoddsmap::LogOddsParameter;
+
+/// Model for integrating the scans into the map
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MapModel {
+    /// Ratio of hits and misses of each cell
+    #[default]
+    Frequency,
+    /// Bayesian log-odds occupancy grid
+    LogOdds,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct MapCreatorParameter {
     ///< the max range of the laser scanner data
     pub max_range: f64,
@@ -9,6 +26,7 @@ pub struct MapCreatorParameter {
     ///< resolution of the map
     pub resolution: f64,
     ///< offset of the map, can be automatically set to zero
+    #[serde(with = "offset_degrees")]
     pub offset: na::Isometry2<f64>,
     ///< border around the map which is set to unknown
     pub border: f64,
@@ -17,7 +35,21 @@ pub struct MapCreatorParameter {
     ///< set the first pose of the map automatically to zero
     pub zero_first_pose: bool,
     ///< print some verbose information while creating the map
+    #[serde(skip)]
     pub verbose: bool,
+    ///< print the verbose information to stderr, e.g., if stdout receives the output
+    #[serde(skip)]
+    pub verbose_stderr: bool,
+    ///< model used to integrate the scans into the map
+    pub map_model: MapModel,
+    ///< inverse sensor model of the log-odds map
+    pub log_odds: LogOddsParameter,
+    ///< number of threads for integrating the scans, 0 uses all cores, tiled maps use one
+    pub threads: usize,
+    ///< grow the map in tiles instead of allocating the boundaries up front
+    pub tiled: bool,
+    ///< algorithm for finding the cells along a beam
+    pub ray_tracer: RayTracer,
 }
 
 impl Default for MapCreatorParameter {
@@ -31,6 +63,36 @@ impl Default for MapCreatorParameter {
             path_width: 0.2,
             zero_first_pose: false,
             verbose: false,
+            verbose_stderr: false,
+            map_model: MapModel::default(),
+            log_odds: LogOddsParameter::default(),
+            threads: 1,
+            tiled: false,
+            ray_tracer: RayTracer::default(),
         }
     }
 }
+
+/// Store the offset as `[x, y, theta]` with the angle in degrees
+mod offset_degrees {
+    use nalgebra as na;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        offset: &na::Isometry2<f64>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let t = offset.translation;
+        [t.x, t.y, offset.rotation.angle().to_degrees()].serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<na::Isometry2<f64>, D::Error> {
+        let [x, y, theta] = <[f64; 3]>::deserialize(deserializer)?;
+        Ok(na::Isometry2::new(
+            na::Vector2::new(x, y),
+            theta.to_radians(),
+        ))
+    }
+}
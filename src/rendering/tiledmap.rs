@@ -0,0 +1,142 @@
+extern crate nalgebra as na;
+
+use std::collections::HashMap;
+
+use super::gridmap::{CellRegion, GridAccess, GridMap, GridStorage, MapTransform};
+
+/// Number of cells along each side of a tile
+pub const TILE_SIZE: i32 = 64;
+
+/// Grid map without fixed bounds which allocates square tiles of cells when
+/// a cell of a tile is modified for the first time.
+///
+/// The cell (0, 0) starts at the origin of the world.
+pub struct TiledGridMap<T> {
+    pub resolution: f64,
+    ///< cells of unknown space added around the touched cells by `to_dense`
+    pub border: usize,
+    unknown_cell: T,
+    tiles: HashMap<[i32; 2], Vec<T>>,
+    touched: CellRegion,
+}
+
+/// Index of the tile and of the cell within the tile
+fn tile_index(x: i32, y: i32) -> ([i32; 2], usize) {
+    let tile = [x.div_euclid(TILE_SIZE), y.div_euclid(TILE_SIZE)];
+    let cell = y.rem_euclid(TILE_SIZE) * TILE_SIZE + x.rem_euclid(TILE_SIZE);
+    (tile, cell as usize)
+}
+
+impl<T: Copy> TiledGridMap<T> {
+    pub fn new(resolution: f64, border: usize, unknown_cell: T) -> Self {
+        Self {
+            resolution,
+            border,
+            unknown_cell,
+            tiles: HashMap::new(),
+            touched: CellRegion::empty(),
+        }
+    }
+
+    pub fn num_tiles(&self) -> usize {
+        self.tiles.len()
+    }
+
+    /// Region of all the cells which have been modified
+    pub fn touched(&self) -> CellRegion {
+        self.touched
+    }
+
+    pub fn cell(&self, x: i32, y: i32) -> Option<&T> {
+        let (tile, cell) = tile_index(x, y);
+        self.tiles.get(&tile).map(|t| &t[cell])
+    }
+
+    /// Access a cell and allocate its tile if necessary
+    pub fn cell_mut(&mut self, x: i32, y: i32) -> &mut T {
+        self.touched.extend(&na::Vector2::new(x, y));
+        let (tile, cell) = tile_index(x, y);
+        let unknown_cell = self.unknown_cell;
+        let tile = self
+            .tiles
+            .entry(tile)
+            .or_insert_with(|| vec![unknown_cell; (TILE_SIZE * TILE_SIZE) as usize]);
+        &mut tile[cell]
+    }
+
+    /// The cells are aligned with the origin of the world
+    pub fn transform(&self) -> MapTransform {
+        MapTransform::new(self.resolution, na::Vector2::zeros())
+    }
+
+    pub fn world2map(&self, wp: &na::Vector2<f64>) -> na::Vector2<i32> {
+        self.transform().world2map(wp)
+    }
+}
+
+impl<T: Copy> GridAccess<T> for TiledGridMap<T> {
+    fn transform(&self) -> MapTransform {
+        TiledGridMap::transform(self)
+    }
+
+    fn cell_mut(&mut self, x: i32, y: i32) -> Option<&mut T> {
+        Some(TiledGridMap::cell_mut(self, x, y))
+    }
+}
+
+impl<T: Copy> GridStorage<T> for TiledGridMap<T> {
+    fn cell(&self, x: i32, y: i32) -> Option<&T> {
+        TiledGridMap::cell(self, x, y)
+    }
+
+    /// Dense map of the touched cells and the border around them
+    fn to_dense<U: Copy, F: Fn(&T) -> U>(&self, unknown_cell: U, convert: F) -> GridMap<U> {
+        if self.touched.is_empty() {
+            return GridMap::new([0, 0], self.resolution, na::Vector2::zeros(), unknown_cell);
+        }
+        let border = self.border as i32;
+        let min = self.touched.min.add_scalar(-border);
+        let max = self.touched.max.add_scalar(border);
+        let size = [(max.x - min.x + 1) as usize, (max.y - min.y + 1) as usize];
+        let offset = min.cast::<f64>() * self.resolution;
+        let mut map = GridMap::new(size, self.resolution, offset, unknown_cell);
+        for (tile, cells) in self.tiles.iter() {
+            for (i, cell) in cells.iter().enumerate() {
+                let x = tile[0] * TILE_SIZE + i as i32 % TILE_SIZE;
+                let y = tile[1] * TILE_SIZE + i as i32 / TILE_SIZE;
+                if let Some(c) = map.cell_mut(x - min.x, y - min.y) {
+                    *c = convert(cell);
+                }
+            }
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocate_tiles() {
+        let mut map = TiledGridMap::new(0.1, 1, 0);
+        assert_eq!(
+            map.world2map(&na::Vector2::new(-0.05, 0.05)),
+            na::Vector2::new(-1, 0)
+        );
+        *map.cell_mut(-1, 0) = 1;
+        *map.cell_mut(3, 2) = 2;
+        *map.cell_mut(200, 2) = 3;
+        assert_eq!(map.num_tiles(), 3);
+        assert_eq!(map.cell(3, 2), Some(&2));
+        assert_eq!(map.cell(-500, 0), None);
+
+        let dense = map.to_dense(-1, |c| *c);
+        assert_eq!(dense.size, [204, 5]);
+        assert!((dense.offset - na::Vector2::new(-0.2, -0.1)).norm() < 1e-9);
+        assert_eq!(dense.cell(5, 3), Some(&2));
+        assert_eq!(dense.cell(202, 3), Some(&3));
+        assert_eq!(dense.cell(4, 3), Some(&0));
+        assert_eq!(dense.cell(100, 3), Some(&-1));
+    }
+}
@@ -0,0 +1,278 @@
+extern crate nalgebra as na;
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::datastream::robot_data::RobotLaser;
+use crate::drawing::map_server::MapServerParameter;
+use crate::rendering::floatmap::FloatMap;
+
+/// Upper bound for the number of intervals of a range histogram
+pub const MAX_BINS: usize = 10000;
+
+/// The width of the range intervals is not positive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BinWidthError(pub f64);
+
+impl fmt::Display for BinWidthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the bin width of the range histogram has to be positive, got {}",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for BinWidthError {}
+
+/// Number of readings per range interval
+#[derive(Debug, Clone, Serialize)]
+pub struct RangeHistogram {
+    ///< width of an interval in meter
+    pub bin_width: f64,
+    ///< readings in [i * bin_width, (i + 1) * bin_width), at most `MAX_BINS` intervals
+    pub counts: Vec<usize>,
+    ///< readings below the max range but beyond the last of the `MAX_BINS` intervals
+    pub beyond_bins: usize,
+    ///< readings at or beyond the max range of the laser, not in `counts`
+    pub max_range: usize,
+}
+
+impl RangeHistogram {
+    /// Empty histogram with intervals of `bin_width` meter, which has to be positive
+    pub fn new(bin_width: f64) -> Result<Self, BinWidthError> {
+        if !(bin_width > 0. && bin_width.is_finite()) {
+            return Err(BinWidthError(bin_width));
+        }
+        Ok(Self {
+            bin_width,
+            counts: Vec::new(),
+            beyond_bins: 0,
+            max_range: 0,
+        })
+    }
+
+    pub fn add(&mut self, range: f32, max_range: f32) {
+        if range >= max_range || !range.is_finite() {
+            self.max_range += 1;
+            return;
+        }
+        // the cast saturates for huge ranges of a corrupt reading
+        let bin = (range.max(0.) as f64 / self.bin_width) as usize;
+        if bin >= MAX_BINS {
+            self.beyond_bins += 1;
+            return;
+        }
+        if bin >= self.counts.len() {
+            self.counts.resize(bin + 1, 0);
+        }
+        self.counts[bin] += 1;
+    }
+}
+
+/// Statistics of the trajectory which are collected while streaming the scans
+#[derive(Debug, Clone, Serialize)]
+pub struct TrajectoryStats {
+    pub num_scans: usize,
+    ///< length of the path of the robot in meter
+    pub length: f64,
+    ///< timestamps of the first and the last scan
+    pub time_span: Option<(f64, f64)>,
+    ///< ranges of all the beams
+    pub ranges: RangeHistogram,
+    #[serde(skip)]
+    last_pose: Option<na::Isometry2<f64>>,
+}
+
+impl Default for TrajectoryStats {
+    fn default() -> Self {
+        Self::new(1.).unwrap()
+    }
+}
+
+impl TrajectoryStats {
+    /// Statistics with range intervals of `bin_width` meter
+    pub fn new(bin_width: f64) -> Result<Self, BinWidthError> {
+        Ok(Self {
+            num_scans: 0,
+            length: 0.,
+            time_span: None,
+            ranges: RangeHistogram::new(bin_width)?,
+            last_pose: None,
+        })
+    }
+
+    pub fn add(&mut self, rl: &RobotLaser) {
+        self.num_scans += 1;
+        if let Some(last_pose) = self.last_pose {
+            self.length += (rl.odom_pose.translation.vector - last_pose.translation.vector).norm();
+        }
+        self.last_pose = Some(rl.odom_pose);
+        let first = self.time_span.map_or(rl.timestamp(), |t| t.0);
+        self.time_span = Some((first, rl.timestamp()));
+        for r in rl.ranges.iter() {
+            self.ranges.add(*r, rl.laser_params.max_range as f32);
+        }
+    }
+}
+
+impl fmt::Display for TrajectoryStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Number of laser readings: {}", self.num_scans)?;
+        writeln!(f, "Trajectory length: {:.3} m", self.length)?;
+        if let Some((first, last)) = self.time_span {
+            writeln!(f, "Time span: {:.3} s", last - first)?;
+        }
+        Ok(())
+    }
+}
+
+/// Size of an occupancy map and the number of its cells by state
+#[derive(Debug, Clone, Serialize)]
+pub struct MapStats {
+    pub resolution: f64,
+    ///< number of cells along x and y
+    pub size: [usize; 2],
+    ///< lower left and upper right corner of the map in world coordinates, `None` for an empty map
+    pub bounding_box: Option<[[f64; 2]; 2]>,
+    ///< cells which have been observed
+    pub known_cells: usize,
+    ///< known cells with an occupancy below the free threshold
+    pub free_cells: usize,
+    ///< known cells with an occupancy above the occupied threshold
+    pub occupied_cells: usize,
+    ///< area of the known cells in square meter
+    pub explored_area: f64,
+}
+
+impl MapStats {
+    /// Count the cells of a map, the thresholds are those of the map_server export
+    pub fn new(fmap: &FloatMap, thresholds: &MapServerParameter) -> Self {
+        let map = &fmap.map;
+        let empty = map.size[0] == 0 || map.size[1] == 0;
+        let mut stats = Self {
+            resolution: map.resolution,
+            size: map.size,
+            bounding_box: (!empty).then(|| {
+                [
+                    [map.offset.x, map.offset.y],
+                    [
+                        map.offset.x + map.size[0] as f64 * map.resolution,
+                        map.offset.y + map.size[1] as f64 * map.resolution,
+                    ],
+                ]
+            }),
+            known_cells: 0,
+            free_cells: 0,
+            occupied_cells: 0,
+            explored_area: 0.,
+        };
+        for y in 0..map.size[1] as i32 {
+            for x in 0..map.size[0] as i32 {
+                let occ = *map.cell(x, y).unwrap() as f64;
+                if occ < 0. {
+                    continue;
+                }
+                stats.known_cells += 1;
+                if occ > thresholds.occupied_thresh {
+                    stats.occupied_cells += 1;
+                } else if occ < thresholds.free_thresh {
+                    stats.free_cells += 1;
+                }
+            }
+        }
+        stats.explored_area = stats.known_cells as f64 * map.resolution * map.resolution;
+        stats
+    }
+}
+
+/// Statistics of a log and the map built from it
+#[derive(Debug, Clone, Serialize)]
+pub struct Statistics {
+    pub trajectory: TrajectoryStats,
+    pub map: MapStats,
+}
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (trajectory, map) = (&self.trajectory, &self.map);
+        write!(f, "{}", trajectory)?;
+        match map.bounding_box {
+            Some([min, max]) => writeln!(
+                f,
+                "Bounding box: [{:.3}, {:.3}] - [{:.3}, {:.3}] m",
+                min[0], min[1], max[0], max[1]
+            )?,
+            None => writeln!(f, "Bounding box: none, the map is empty")?,
+        }
+        writeln!(
+            f,
+            "Map size: {} x {} cells at {} m",
+            map.size[0], map.size[1], map.resolution
+        )?;
+        writeln!(
+            f,
+            "Known cells: {} ({} free, {} occupied)",
+            map.known_cells, map.free_cells, map.occupied_cells
+        )?;
+        writeln!(f, "Explored area: {:.3} m²", map.explored_area)?;
+        writeln!(f, "Ranges:")?;
+        let ranges = &trajectory.ranges;
+        for (i, count) in ranges.counts.iter().enumerate() {
+            let from = i as f64 * ranges.bin_width;
+            writeln!(
+                f,
+                "  {:>7.2} - {:>7.2} m: {}",
+                from,
+                from + ranges.bin_width,
+                count
+            )?;
+        }
+        if ranges.beyond_bins > 0 {
+            writeln!(
+                f,
+                "  {:>7.2} - max range: {}",
+                MAX_BINS as f64 * ranges.bin_width,
+                ranges.beyond_bins
+            )?;
+        }
+        writeln!(f, "  max range: {}", ranges.max_range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::gridmap::GridMap;
+
+    #[test]
+    fn count_cells_and_ranges() {
+        let mut map = GridMap::new([4, 2], 0.5, na::Vector2::new(-1., 0.), -1.);
+        *map.cell_mut(0, 0).unwrap() = 0.;
+        *map.cell_mut(1, 0).unwrap() = 0.5;
+        *map.cell_mut(2, 1).unwrap() = 1.;
+        let stats = MapStats::new(&FloatMap { map }, &MapServerParameter::default());
+        assert_eq!(stats.known_cells, 3);
+        assert_eq!((stats.free_cells, stats.occupied_cells), (1, 1));
+        assert_eq!(stats.explored_area, 0.75);
+        assert_eq!(stats.bounding_box, Some([[-1., 0.], [1., 1.]]));
+        let empty = GridMap::new([0, 0], 0.5, na::Vector2::zeros(), -1.);
+        let stats = MapStats::new(&FloatMap { map: empty }, &MapServerParameter::default());
+        assert_eq!(stats.bounding_box, None);
+
+        let mut ranges = RangeHistogram::new(2.).unwrap();
+        for r in [0.5, 1.9, 2., 5.5, 20.] {
+            ranges.add(r, 20.);
+        }
+        assert_eq!(ranges.counts, [2, 1, 1]);
+        assert_eq!(ranges.max_range, 1);
+        // a corrupt reading far below a corrupt max range
+        ranges.add(1e20, 1e30);
+        assert_eq!(ranges.counts.len(), 3);
+        assert_eq!(ranges.beyond_bins, 1);
+        assert!(RangeHistogram::new(0.).is_err());
+        assert!(RangeHistogram::new(f64::NAN).is_err());
+    }
+}